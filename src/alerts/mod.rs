@@ -0,0 +1,116 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use log::info;
+use crate::api::{Currency, Item};
+
+mod watchlist;
+mod webhook;
+
+pub use watchlist::{percent_from_avg_24h, AlertCondition, WatchEntry, Watchlist};
+pub use webhook::WebhookSink;
+
+/// A watched item meeting one of its conditions.
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceAlert {
+    pub uid: String,
+    pub name: String,
+    pub condition: AlertCondition,
+    pub price: i32,
+    pub avg_24h_price: i32,
+    /// e.g. "LEDX: 1,250,000₽ (above 1,000,000₽)"
+    pub message: String,
+}
+
+/// Evaluates the watchlist against each price refresh and publishes alerts
+/// to subscribers such as the overlay and the webhook sink.
+///
+/// Alerts fire when a condition starts being met, not on every refresh it
+/// stays met, so a price sitting above a threshold alerts once.
+pub struct AlertEngine {
+    watchlist: Arc<Watchlist>,
+    // (uid, condition) pairs met at the last evaluation
+    active: Mutex<HashSet<(String, String)>>,
+    events: broadcast::Sender<PriceAlert>,
+}
+
+impl AlertEngine {
+    pub fn new(watchlist: Arc<Watchlist>) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            watchlist,
+            active: Mutex::new(HashSet::new()),
+            events,
+        }
+    }
+
+    pub fn watchlist(&self) -> &Arc<Watchlist> {
+        &self.watchlist
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PriceAlert> {
+        self.events.subscribe()
+    }
+
+    /// Checks the watched items of a refreshed item list and publishes the
+    /// conditions that became met since the last evaluation. Watched items
+    /// missing from the list keep their state.
+    pub fn evaluate(&self, items: &[Item]) -> Vec<PriceAlert> {
+        let entries = self.watchlist.entries();
+        if entries.is_empty() {
+            return Vec::new();
+        }
+
+        let items: HashMap<&str, &Item> = items.iter().map(|item| (item.uid.as_str(), item)).collect();
+        let mut active = self.active.lock().unwrap();
+        let mut alerts = Vec::new();
+
+        for entry in &entries {
+            let Some(item) = items.get(entry.uid.as_str()) else {
+                continue;
+            };
+
+            for condition in &entry.conditions {
+                let key = (entry.uid.clone(), condition.describe());
+                if !condition.is_met(item) {
+                    active.remove(&key);
+                    continue;
+                }
+                if !active.insert(key) {
+                    continue;
+                }
+
+                alerts.push(PriceAlert {
+                    uid: item.uid.clone(),
+                    name: item.name.clone(),
+                    condition: condition.clone(),
+                    price: item.price,
+                    avg_24h_price: item.avg_24h_price,
+                    message: alert_message(item, condition),
+                });
+            }
+        }
+
+        // Forget conditions of unwatched items so re-pinning alerts again
+        active.retain(|(uid, _)| entries.iter().any(|entry| &entry.uid == uid));
+        drop(active);
+
+        for alert in &alerts {
+            info!("Price alert: {}", alert.message);
+            let _ = self.events.send(alert.clone());
+        }
+        alerts
+    }
+}
+
+fn alert_message(item: &Item, condition: &AlertCondition) -> String {
+    let price = Currency::Rub.format(item.price as i64);
+    match condition {
+        AlertCondition::DeviatesFromAvg24h { .. } => {
+            let change = percent_from_avg_24h(item).unwrap_or(0.0);
+            format!("{}: {} ({:+.1}% vs 24h avg)", item.name, price, change)
+        },
+        _ => format!("{}: {} ({})", item.name, price, condition.describe()),
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use log::info;
+use crate::api::{Currency, Item, PriceProvider};
+
+const WATCHLIST_FILE: &str = "watchlist.json";
+
+/// When a watched item raises an alert.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Flea price rises to or above `price`
+    Above { price: i32 },
+    /// Flea price falls to or below `price`
+    Below { price: i32 },
+    /// Flea price is at least `percent` away from the 24h average, either way
+    DeviatesFromAvg24h { percent: f64 },
+}
+
+impl AlertCondition {
+    pub fn is_met(&self, item: &Item) -> bool {
+        if item.price <= 0 {
+            return false;
+        }
+
+        match self {
+            AlertCondition::Above { price } => item.price >= *price,
+            AlertCondition::Below { price } => item.price <= *price,
+            AlertCondition::DeviatesFromAvg24h { percent } => {
+                matches!(percent_from_avg_24h(item), Some(change) if change.abs() >= *percent)
+            },
+        }
+    }
+
+    /// "above 100,000₽", "below 50,000₽" or "±20% vs 24h avg"
+    pub fn describe(&self) -> String {
+        match self {
+            AlertCondition::Above { price } => format!("above {}", Currency::Rub.format(*price as i64)),
+            AlertCondition::Below { price } => format!("below {}", Currency::Rub.format(*price as i64)),
+            AlertCondition::DeviatesFromAvg24h { percent } => format!("±{}% vs 24h avg", percent),
+        }
+    }
+}
+
+/// Change of the flea price versus the 24h average in percent, `None` when
+/// the average is unknown.
+pub fn percent_from_avg_24h(item: &Item) -> Option<f64> {
+    if item.avg_24h_price <= 0 {
+        return None;
+    }
+    Some((item.price - item.avg_24h_price) as f64 / item.avg_24h_price as f64 * 100.0)
+}
+
+/// A pinned item and the conditions it's watched for.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchEntry {
+    pub uid: String,
+    /// Name at the time the item was pinned, for alerts and the UI
+    pub name: String,
+    pub conditions: Vec<AlertCondition>,
+}
+
+/// Items the user pinned for price alerts, persisted to `watchlist.json` in
+/// the data directory.
+pub struct Watchlist {
+    path: PathBuf,
+    entries: RwLock<Vec<WatchEntry>>,
+}
+
+impl Watchlist {
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let path = data_dir.as_ref().join(WATCHLIST_FILE);
+        let entries: Vec<WatchEntry> = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .context(format!("Failed to read watchlist: {:?}", path))?;
+            serde_json::from_str(&contents)
+                .context(format!("Failed to parse watchlist: {:?}", path))?
+        } else {
+            Vec::new()
+        };
+
+        info!("Loaded {} watched items", entries.len());
+        Ok(Self {
+            path,
+            entries: RwLock::new(entries),
+        })
+    }
+
+    pub fn entries(&self) -> Vec<WatchEntry> {
+        self.entries.read().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.read().unwrap().is_empty()
+    }
+
+    /// Pins an item, replacing the conditions if it's already watched, and
+    /// saves the watchlist.
+    pub fn watch(&self, entry: WatchEntry) -> Result<()> {
+        {
+            let mut entries = self.entries.write().unwrap();
+            match entries.iter_mut().find(|existing| existing.uid == entry.uid) {
+                Some(existing) => *existing = entry,
+                None => entries.push(entry),
+            }
+        }
+        self.save()
+    }
+
+    /// Pins the best match of a search, see `watch`.
+    pub async fn watch_search<P: PriceProvider>(
+        &self,
+        provider: &P,
+        query: &str,
+        conditions: Vec<AlertCondition>,
+    ) -> Result<WatchEntry> {
+        let item = match provider.search_offline(query, 1).await.into_iter().next() {
+            Some(hit) => hit.item,
+            None => provider.search_item(query).await
+                .context(format!("Failed to search {:?}", query))?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No item matches {:?}", query))?,
+        };
+
+        let entry = WatchEntry {
+            uid: item.uid,
+            name: item.name,
+            conditions,
+        };
+        self.watch(entry.clone())?;
+        Ok(entry)
+    }
+
+    /// Unpins an item and saves the watchlist. Returns whether it was watched.
+    pub fn unwatch(&self, uid: &str) -> Result<bool> {
+        let removed = {
+            let mut entries = self.entries.write().unwrap();
+            let len = entries.len();
+            entries.retain(|entry| entry.uid != uid);
+            entries.len() < len
+        };
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<()> {
+        let contents = serde_json::to_string_pretty(&*self.entries.read().unwrap())?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .context(format!("Failed to create data directory: {:?}", dir))?;
+        }
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents)
+            .context(format!("Failed to write watchlist: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::{Result, Context};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use log::{info, warn};
+use super::PriceAlert;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Posts every alert as JSON to a local webhook, e.g. a chat bot or stream
+/// automation listening on `http://127.0.0.1:<port>/...`.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+        }
+    }
+
+    pub async fn send(&self, alert: &PriceAlert) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(alert)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context(format!("Failed to post alert to {}", self.url))?;
+        Ok(())
+    }
+
+    /// Forwards alerts until the engine shuts down. Failed posts are logged
+    /// and dropped, the alert still shows on screen.
+    pub async fn run(self, mut alerts: broadcast::Receiver<PriceAlert>) {
+        info!("Forwarding price alerts to {}", self.url);
+        loop {
+            match alerts.recv().await {
+                Ok(alert) => {
+                    if let Err(e) = self.send(&alert).await {
+                        warn!("{:#}", e);
+                    }
+                },
+                Err(RecvError::Lagged(skipped)) => warn!("Webhook fell behind, {} alerts dropped", skipped),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use log::info;
+use super::Item;
+
+const AMMO_FILE: &str = "ammo.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AmmoStats {
+    /// e.g. "5.45x39mm"
+    pub caliber: String,
+    /// Flesh damage per projectile
+    pub damage: u32,
+    pub penetration: u32,
+    /// Armor damage in percent
+    pub armor_damage: u32,
+    /// Pellets per shot for buckshot, 1 otherwise
+    #[serde(default = "default_projectile_count")]
+    pub projectile_count: u32,
+}
+
+fn default_projectile_count() -> u32 {
+    1
+}
+
+impl AmmoStats {
+    /// Compact tooltip line, e.g. "5.45x39mm 40 dmg / 51 pen / 57% AD" or
+    /// "12/70 8x25 dmg / 2 pen / 65% AD" for buckshot.
+    pub fn summary(&self) -> String {
+        let damage = if self.projectile_count > 1 {
+            format!("{}x{}", self.projectile_count, self.damage)
+        } else {
+            self.damage.to_string()
+        };
+        format!("{} {} dmg / {} pen / {}% AD", self.caliber, damage, self.penetration, self.armor_damage)
+    }
+}
+
+/// Offline ammo stats for backends that don't report them (Tarkov Market),
+/// read from `ammo.json` in the data directory:
+///
+/// ```json
+/// { "56dff3afd2720bba668b4567": { "caliber": "5.45x39mm", "damage": 40, "penetration": 51, "armor_damage": 57 } }
+/// ```
+#[derive(Debug, Default)]
+pub struct AmmoTable {
+    stats: HashMap<String, AmmoStats>,
+}
+
+impl AmmoTable {
+    /// Loads the ammo file, or an empty table if there is none.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let path = data_dir.as_ref().join(AMMO_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .context(format!("Failed to read ammo stats: {:?}", path))?;
+        let stats: HashMap<String, AmmoStats> = serde_json::from_str(&contents)
+            .context(format!("Failed to parse ammo stats: {:?}", path))?;
+
+        info!("Loaded stats for {} ammo types", stats.len());
+        Ok(Self { stats })
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+
+    pub fn get(&self, uid: &str) -> Option<&AmmoStats> {
+        self.stats.get(uid)
+    }
+
+    /// Fills in the ammo stats of items the backend didn't report them for.
+    pub fn apply(&self, item: &mut Item) {
+        if item.ammo.is_none() {
+            item.ammo = self.get(&item.uid).cloned();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{CacheStats, ExchangeRates, Item, MarketMode, PriceProvider, Recipe, Result, SearchHit, TarkovDevAPI, TarkovMarketAPI};
+use crate::config::Config;
+
+/// Price backends selectable with `Config::provider`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// api.tarkov-market.app, needs `Config::api_key`
+    #[default]
+    TarkovMarket,
+    /// The community tarkov.dev GraphQL API, no key needed
+    TarkovDev,
+}
+
+impl ProviderKind {
+    pub fn requires_api_key(&self) -> bool {
+        matches!(self, ProviderKind::TarkovMarket)
+    }
+}
+
+/// The price backend chosen in the config. Lets the plugin hold either
+/// client without being generic over `PriceProvider` itself.
+pub enum PriceBackend {
+    TarkovMarket(TarkovMarketAPI),
+    TarkovDev(TarkovDevAPI),
+}
+
+impl PriceBackend {
+    pub fn from_config(config: &Config) -> Self {
+        match config.provider {
+            ProviderKind::TarkovMarket => PriceBackend::TarkovMarket(TarkovMarketAPI::from_config(config)),
+            ProviderKind::TarkovDev => PriceBackend::TarkovDev(TarkovDevAPI::from_config(config)),
+        }
+    }
+
+    /// Drops long expired cache entries, see `CacheSettings::stale_retention`.
+    pub async fn evict_expired(&self) -> usize {
+        match self {
+            PriceBackend::TarkovMarket(api) => api.evict_expired().await,
+            PriceBackend::TarkovDev(api) => api.evict_expired().await,
+        }
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        match self {
+            PriceBackend::TarkovMarket(api) => api.cache_stats(),
+            PriceBackend::TarkovDev(api) => api.cache_stats(),
+        }
+    }
+
+    /// Ammo types with offline stats from `ammo.json`, `None` for backends
+    /// that report the stats with the items.
+    pub fn offline_ammo_types(&self) -> Option<usize> {
+        match self {
+            PriceBackend::TarkovMarket(api) => Some(api.ammo_types()),
+            PriceBackend::TarkovDev(_) => None,
+        }
+    }
+}
+
+impl PriceProvider for PriceBackend {
+    fn name(&self) -> &str {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::name(api),
+            PriceBackend::TarkovDev(api) => PriceProvider::name(api),
+        }
+    }
+
+    async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::search_item(api, query).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::search_item(api, query).await,
+        }
+    }
+
+    async fn get_item_by_uid(&self, uid: &str) -> Result<Item> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::get_item_by_uid(api, uid).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::get_item_by_uid(api, uid).await,
+        }
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<Item>> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::get_all_items(api).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::get_all_items(api).await,
+        }
+    }
+
+    async fn get_items_by_uids(&self, uids: &[String]) -> Result<HashMap<String, Item>> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::get_items_by_uids(api, uids).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::get_items_by_uids(api, uids).await,
+        }
+    }
+
+    async fn refresh_all_items(&self) -> Result<Vec<Item>> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::refresh_all_items(api).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::refresh_all_items(api).await,
+        }
+    }
+
+    fn remaining_quota(&self) -> Option<u32> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::remaining_quota(api),
+            PriceBackend::TarkovDev(api) => PriceProvider::remaining_quota(api),
+        }
+    }
+
+    fn market_mode(&self) -> MarketMode {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::market_mode(api),
+            PriceBackend::TarkovDev(api) => PriceProvider::market_mode(api),
+        }
+    }
+
+    async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::search_offline(api, query, limit).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::search_offline(api, query, limit).await,
+        }
+    }
+
+    async fn get_exchange_rates(&self) -> Result<ExchangeRates> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::get_exchange_rates(api).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::get_exchange_rates(api).await,
+        }
+    }
+
+    async fn get_recipes(&self) -> Result<Vec<Recipe>> {
+        match self {
+            PriceBackend::TarkovMarket(api) => PriceProvider::get_recipes(api).await,
+            PriceBackend::TarkovDev(api) => PriceProvider::get_recipes(api).await,
+        }
+    }
+}
@@ -0,0 +1,800 @@
+use anyhow::{bail, Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::RwLock;
+use log::{info, warn};
+
+use super::{ApiError, Item, ITEM_SCHEMA_VERSION};
+use super::conditional::Validators;
+use crate::config::Config;
+
+// Minimum time between two snapshot writes. Changes in between are written
+// by the next write after it, the periodic eviction or when the cache is
+// dropped.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntry<T> {
+    pub(crate) data: T,
+    /// Wall-clock time so entries stay meaningful across restarts
+    pub(crate) timestamp: SystemTime,
+    /// ETag and Last-Modified of the response the data came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) validators: Option<Validators>,
+}
+
+impl<T> CacheEntry<T> {
+    pub(crate) fn new(data: T) -> Self {
+        Self {
+            data,
+            timestamp: SystemTime::now(),
+            validators: None,
+        }
+    }
+
+    pub(crate) fn with_validators(data: T, validators: Option<Validators>) -> Self {
+        Self {
+            validators,
+            ..Self::new(data)
+        }
+    }
+
+    pub(crate) fn is_expired(&self, ttl: Duration) -> bool {
+        // A timestamp in the future (clock moved backwards) counts as fresh
+        self.timestamp.elapsed().unwrap_or(Duration::ZERO) > ttl
+    }
+}
+
+/// Size and lifetime limits of the price cache.
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub item_ttl: Duration,
+    pub search_ttl: Duration,
+    pub all_items_ttl: Duration,
+    /// Maximum number of entries per cache (items and searches separately)
+    pub max_entries: usize,
+    /// How long expired entries are kept as offline fallback before the
+    /// background eviction removes them
+    pub stale_retention: Duration,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            item_ttl: Duration::from_secs(300),
+            search_ttl: Duration::from_secs(300),
+            all_items_ttl: Duration::from_secs(3600),
+            max_entries: 5000,
+            stale_retention: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            item_ttl: Duration::from_secs(config.cache_item_ttl_secs),
+            search_ttl: Duration::from_secs(config.cache_search_ttl_secs),
+            all_items_ttl: Duration::from_secs(config.cache_all_items_ttl_secs),
+            max_entries: config.cache_max_entries,
+            stale_retention: Duration::from_secs(config.cache_stale_retention_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+/// Map with least recently used eviction once `capacity` is reached.
+struct LruMap<T> {
+    entries: HashMap<String, (CacheEntry<T>, u64)>,
+    // Access tick -> key, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<T> LruMap<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&CacheEntry<T>> {
+        self.get_mut(key).map(|entry| &*entry)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut CacheEntry<T>> {
+        self.tick += 1;
+        let (entry, last_access) = self.entries.get_mut(key)?;
+        self.order.remove(last_access);
+        *last_access = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(entry)
+    }
+
+    /// Inserts an entry and returns the keys evicted to make room.
+    fn insert(&mut self, key: String, entry: CacheEntry<T>) -> Vec<String> {
+        self.tick += 1;
+        if let Some((_, last_access)) = self.entries.insert(key.clone(), (entry, self.tick)) {
+            self.order.remove(&last_access);
+        }
+        self.order.insert(self.tick, key);
+
+        let mut evicted = Vec::new();
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    /// Removes entries older than `max_age` and returns their keys.
+    fn remove_older_than(&mut self, max_age: Duration) -> Vec<String> {
+        let expired: Vec<String> = self.entries.iter()
+            .filter(|(_, (entry, _))| entry.is_expired(max_age))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            if let Some((_, last_access)) = self.entries.remove(key) {
+                self.order.remove(&last_access);
+            }
+        }
+        expired
+    }
+
+    /// Removes all entries and returns their keys.
+    fn clear(&mut self) -> Vec<String> {
+        self.order.clear();
+        self.entries.drain().map(|(key, _)| key).collect()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn snapshot(&self) -> HashMap<&String, &CacheEntry<T>> {
+        self.entries.iter().map(|(key, (entry, _))| (key, entry)).collect()
+    }
+
+    fn from_snapshot(snapshot: HashMap<String, CacheEntry<T>>, capacity: usize) -> Self {
+        let mut map = Self::new(capacity);
+        // Insert oldest first so the most recent entries survive the capacity limit
+        let mut entries: Vec<_> = snapshot.into_iter().collect();
+        entries.sort_by_key(|(_, entry)| entry.timestamp);
+        for (key, entry) in entries {
+            map.insert(key, entry);
+        }
+        map
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CacheSnapshot {
+    // Missing in snapshots from before the schema was versioned
+    #[serde(default)]
+    version: u32,
+    items: HashMap<String, CacheEntry<Item>>,
+    searches: HashMap<String, CacheEntry<Vec<Item>>>,
+    // Full item lists are stored in files of their own, see `list_path`.
+    // Older snapshots have them in `searches` instead.
+    #[serde(default)]
+    lists: HashMap<String, ListMeta>,
+}
+
+/// Everything of a cached full item list except the items.
+#[derive(Debug, Serialize, Deserialize)]
+struct ListMeta {
+    timestamp: SystemTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validators: Option<Validators>,
+}
+
+#[derive(Debug, Default)]
+struct PersistState {
+    dirty: bool,
+    // Full item lists changed since the last write
+    dirty_lists: HashSet<String>,
+    // Full item lists evicted since the last write, whose files go with it
+    removed_lists: HashSet<String>,
+    last_write: Option<Instant>,
+}
+
+/// Size bounded item and search cache shared by the price clients. When
+/// created with a snapshot path, the previous snapshot is loaded on startup
+/// and changes are written back at most every `PERSIST_INTERVAL`. The large
+/// full item lists go to files of their own, rewritten only when the list
+/// itself changes.
+pub(crate) struct PriceCache {
+    items: RwLock<LruMap<Item>>,
+    searches: RwLock<LruMap<Vec<Item>>>,
+    settings: CacheSettings,
+    snapshot_path: Option<PathBuf>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    // Entry counts kept next to the maps so stats don't need their locks
+    item_entries: AtomicUsize,
+    search_entries: AtomicUsize,
+    persist_state: Mutex<PersistState>,
+    // Keeps two writes from racing for the same temporary files
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl PriceCache {
+    pub(crate) fn in_memory(settings: CacheSettings) -> Self {
+        Self {
+            items: RwLock::new(LruMap::new(settings.max_entries)),
+            searches: RwLock::new(LruMap::new(settings.max_entries)),
+            settings,
+            snapshot_path: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            item_entries: AtomicUsize::new(0),
+            search_entries: AtomicUsize::new(0),
+            persist_state: Mutex::new(PersistState::default()),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn persistent(snapshot_path: PathBuf, settings: CacheSettings) -> Self {
+        let snapshot = match load_snapshot(&snapshot_path) {
+            Ok(snapshot) => {
+                info!(
+                    "Loaded price cache snapshot with {} items, {} searches and {} item lists from {}",
+                    snapshot.items.len(),
+                    snapshot.searches.len(),
+                    snapshot.lists.len(),
+                    snapshot_path.display()
+                );
+                snapshot
+            },
+            Err(e) => {
+                warn!("Starting with an empty price cache: {}", e);
+                CacheSnapshot::default()
+            }
+        };
+
+        // Lists still stored inside an older snapshot get their own files
+        // with the next write
+        let legacy_lists: HashSet<String> = snapshot.searches.keys()
+            .filter(|key| is_all_items_key(key))
+            .cloned()
+            .collect();
+        let mut searches = snapshot.searches;
+        searches.extend(load_lists(&snapshot_path, snapshot.lists));
+
+        let items = LruMap::from_snapshot(snapshot.items, settings.max_entries);
+        let searches = LruMap::from_snapshot(searches, settings.max_entries);
+        let mut cache = Self::in_memory(settings);
+        cache.item_entries = AtomicUsize::new(items.len());
+        cache.search_entries = AtomicUsize::new(searches.len());
+        cache.items = RwLock::new(items);
+        cache.searches = RwLock::new(searches);
+        cache.snapshot_path = Some(snapshot_path);
+        cache.persist_state = Mutex::new(PersistState {
+            dirty: !legacy_lists.is_empty(),
+            dirty_lists: legacy_lists,
+            removed_lists: HashSet::new(),
+            last_write: None,
+        });
+        cache
+    }
+
+    pub(crate) async fn item(&self, key: &str) -> Option<Item> {
+        let mut cache = self.items.write().await;
+        let item = cache.get(key)
+            .filter(|entry| !entry.is_expired(self.settings.item_ttl))
+            .map(|entry| entry.data.clone());
+        self.count_lookup(item.is_some());
+        item
+    }
+
+    /// Returns the cached item regardless of its age, flagged as stale.
+    pub(crate) async fn stale_item(&self, key: &str) -> Option<Item> {
+        let mut cache = self.items.write().await;
+        cache.get(key).map(|entry| Item { stale: true, ..entry.data.clone() })
+    }
+
+    pub(crate) async fn insert_item(&self, key: String, item: Item) {
+        let evicted = {
+            let mut cache = self.items.write().await;
+            let evicted = cache.insert(key, CacheEntry::new(item));
+            self.item_entries.store(cache.len(), Ordering::Relaxed);
+            evicted.len()
+        };
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+        self.persist(None).await;
+    }
+
+    /// Inserts several items keyed by `<scope>:<uid>`, persisting the snapshot once.
+    pub(crate) async fn insert_items(&self, scope: &str, items: Vec<Item>) {
+        {
+            let mut cache = self.items.write().await;
+            for item in items {
+                let evicted = cache.insert(format!("{}:{}", scope, item.uid), CacheEntry::new(item));
+                self.evictions.fetch_add(evicted.len() as u64, Ordering::Relaxed);
+            }
+            self.item_entries.store(cache.len(), Ordering::Relaxed);
+        }
+        self.persist(None).await;
+    }
+
+    pub(crate) async fn search(&self, key: &str) -> Option<Vec<Item>> {
+        self.list(key, self.settings.search_ttl).await
+    }
+
+    /// Returns the cached search result regardless of its age, flagged as stale.
+    pub(crate) async fn stale_search(&self, key: &str) -> Option<Vec<Item>> {
+        let mut cache = self.searches.write().await;
+        cache.get(key).map(|entry| {
+            entry.data.iter()
+                .map(|item| Item { stale: true, ..item.clone() })
+                .collect()
+        })
+    }
+
+    pub(crate) async fn insert_search(&self, key: String, items: Vec<Item>) {
+        self.insert_list(key, CacheEntry::new(items)).await;
+    }
+
+    // Inserts into the search map and persists the snapshot
+    async fn insert_list(&self, key: String, entry: CacheEntry<Vec<Item>>) {
+        let changed_list = is_all_items_key(&key).then(|| key.clone());
+        let evicted = {
+            let mut cache = self.searches.write().await;
+            let evicted = cache.insert(key, entry);
+            self.search_entries.store(cache.len(), Ordering::Relaxed);
+            evicted
+        };
+        self.evictions.fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        self.remove_lists(evicted);
+        self.persist(changed_list).await;
+    }
+
+    /// The full item list of a cache scope (e.g. a language).
+    pub(crate) async fn all_items(&self, scope: &str) -> Option<Vec<Item>> {
+        self.list(&all_items_key(scope), self.settings.all_items_ttl).await
+    }
+
+    pub(crate) async fn stale_all_items(&self, scope: &str) -> Option<Vec<Item>> {
+        self.stale_search(&all_items_key(scope)).await
+    }
+
+    pub(crate) async fn insert_all_items(&self, scope: &str, items: Vec<Item>) {
+        self.insert_search(all_items_key(scope), items).await;
+    }
+
+    /// Stores the item list together with the validators of its response.
+    pub(crate) async fn insert_validated_all_items(&self, scope: &str, items: Vec<Item>, validators: Option<Validators>) {
+        let entry = CacheEntry::with_validators(items, validators);
+        self.insert_list(all_items_key(scope), entry).await;
+    }
+
+    /// Validators of the cached item list, even an expired one, to make the
+    /// next download conditional.
+    pub(crate) async fn all_items_validators(&self, scope: &str) -> Option<Validators> {
+        let mut cache = self.searches.write().await;
+        cache.get(&all_items_key(scope)).and_then(|entry| entry.validators.clone())
+    }
+
+    /// Marks the cached item list as fresh again after the server answered
+    /// 304 Not Modified, and returns it. `None` if it was evicted meanwhile.
+    pub(crate) async fn revalidate_all_items(&self, scope: &str) -> Option<Vec<Item>> {
+        let items = {
+            let mut cache = self.searches.write().await;
+            let entry = cache.get_mut(&all_items_key(scope))?;
+            entry.timestamp = SystemTime::now();
+            entry.data.clone()
+        };
+        // Only the timestamp changed, the list file stays as it is
+        self.persist(None).await;
+        Some(items)
+    }
+
+    async fn list(&self, key: &str, ttl: Duration) -> Option<Vec<Item>> {
+        let mut cache = self.searches.write().await;
+        let items = cache.get(key)
+            .filter(|entry| !entry.is_expired(ttl))
+            .map(|entry| entry.data.clone());
+        self.count_lookup(items.is_some());
+        items
+    }
+
+    /// Drops entries that have been expired for longer than the stale
+    /// retention. Returns the number of evicted entries.
+    pub(crate) async fn evict_expired(&self) -> usize {
+        let settings = &self.settings;
+        let evicted = {
+            let mut items = self.items.write().await;
+            let mut searches = self.searches.write().await;
+            let list_ttl = settings.all_items_ttl.max(settings.search_ttl);
+            let evicted_searches = searches.remove_older_than(list_ttl + settings.stale_retention);
+            let evicted = items.remove_older_than(settings.item_ttl + settings.stale_retention).len()
+                + evicted_searches.len();
+            self.item_entries.store(items.len(), Ordering::Relaxed);
+            self.search_entries.store(searches.len(), Ordering::Relaxed);
+            self.remove_lists(evicted_searches);
+            evicted
+        };
+
+        if evicted > 0 {
+            self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+            self.lock_persist_state().dirty = true;
+        }
+        // Also writes what the last inserts left pending
+        self.flush().await;
+        evicted
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.item_entries.load(Ordering::Relaxed) + self.search_entries.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) async fn clear(&self) {
+        {
+            let mut items = self.items.write().await;
+            let mut searches = self.searches.write().await;
+            items.clear();
+            self.remove_lists(searches.clear());
+            self.item_entries.store(0, Ordering::Relaxed);
+            self.search_entries.store(0, Ordering::Relaxed);
+        }
+        self.persist(None).await;
+    }
+
+    fn count_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn lock_persist_state(&self) -> std::sync::MutexGuard<'_, PersistState> {
+        self.persist_state.lock().unwrap()
+    }
+
+    // Notes the full item lists among removed search keys, so the next write
+    // deletes their files
+    fn remove_lists(&self, removed_keys: Vec<String>) {
+        if self.snapshot_path.is_none() {
+            return;
+        }
+        let mut state = self.lock_persist_state();
+        for key in removed_keys.into_iter().filter(|key| is_all_items_key(key)) {
+            state.dirty_lists.remove(&key);
+            state.removed_lists.insert(key);
+        }
+    }
+
+    // Records a change and writes the snapshot unless the last write was
+    // too recent. `changed_list` is the key of a full item list whose items
+    // changed.
+    async fn persist(&self, changed_list: Option<String>) {
+        if self.snapshot_path.is_none() {
+            return;
+        }
+
+        {
+            let mut state = self.lock_persist_state();
+            state.dirty = true;
+            state.dirty_lists.extend(changed_list);
+            if state.last_write.is_some_and(|at| at.elapsed() < PERSIST_INTERVAL) {
+                return;
+            }
+        }
+        self.flush().await;
+    }
+
+    /// Writes pending changes to disk. The files are written on the
+    /// blocking thread pool, only serializing happens under the cache locks.
+    pub(crate) async fn flush(&self) {
+        let Some(path) = &self.snapshot_path else {
+            return;
+        };
+
+        let _writing = self.write_lock.lock().await;
+        let (dirty_lists, removed_lists) = {
+            let mut state = self.lock_persist_state();
+            if !state.dirty {
+                return;
+            }
+            state.dirty = false;
+            state.last_write = Some(Instant::now());
+            (std::mem::take(&mut state.dirty_lists), std::mem::take(&mut state.removed_lists))
+        };
+
+        let files = {
+            let items = self.items.read().await;
+            let searches = self.searches.read().await;
+            snapshot_files(path, &items, &searches, &dirty_lists, &removed_lists)
+        };
+        let result = match files {
+            Ok(files) => tokio::task::spawn_blocking(move || write_files(files))
+                .await
+                .unwrap_or_else(|e| Err(e.into())),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to persist price cache to {}: {}", path.display(), e);
+            // Try again with the next write
+            let mut state = self.lock_persist_state();
+            state.dirty = true;
+            state.dirty_lists.extend(dirty_lists);
+            state.removed_lists.extend(removed_lists);
+        }
+    }
+}
+
+impl Drop for PriceCache {
+    // Writes what the debounce held back
+    fn drop(&mut self) {
+        let Some(path) = &self.snapshot_path else {
+            return;
+        };
+        let state = self.persist_state.get_mut().unwrap();
+        if !state.dirty {
+            return;
+        }
+
+        let result = snapshot_files(path, self.items.get_mut(), self.searches.get_mut(), &state.dirty_lists, &state.removed_lists)
+            .and_then(write_files);
+        if let Err(e) = result {
+            warn!("Failed to persist price cache to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn all_items_key(scope: &str) -> String {
+    format!("{}:all_items", scope)
+}
+
+fn is_all_items_key(key: &str) -> bool {
+    key.ends_with(":all_items")
+}
+
+// File of a full item list next to the snapshot, e.g.
+// "tarkov-market.pvp_en_all_items.json" for "tarkov-market.json"
+fn list_path(snapshot_path: &Path, key: &str) -> PathBuf {
+    let stem = snapshot_path.file_stem().unwrap_or_default().to_string_lossy();
+    snapshot_path.with_file_name(format!("{}.{}.json", stem, key.replace(':', "_")))
+}
+
+/// Serves a stale cached value when the API was unavailable, or returns the
+/// error if nothing was cached or the API gave a definitive answer.
+pub(crate) fn stale_or_err<T>(stale: Option<T>, err: ApiError) -> Result<T, ApiError> {
+    match stale {
+        Some(data) if err.is_unavailable() => {
+            warn!("Request failed, serving stale cached data: {}", err);
+            Ok(data)
+        },
+        _ => Err(err),
+    }
+}
+
+fn load_snapshot(path: &PathBuf) -> Result<CacheSnapshot> {
+    let snapshot_str = fs::read_to_string(path)
+        .context(format!("Failed to read cache snapshot: {:?}", path))?;
+    let snapshot: CacheSnapshot = serde_json::from_str(&snapshot_str)
+        .context(format!("Failed to parse cache snapshot: {:?}", path))?;
+
+    // Older snapshots read fine thanks to the lenient item schema, but fields
+    // of a newer one may mean something this version doesn't expect
+    if snapshot.version > ITEM_SCHEMA_VERSION {
+        bail!("Cache snapshot {:?} has newer schema version {}", path, snapshot.version);
+    }
+    Ok(snapshot)
+}
+
+// Reads the full item lists of a snapshot, skipping unreadable ones
+fn load_lists(snapshot_path: &Path, lists: HashMap<String, ListMeta>) -> Vec<(String, CacheEntry<Vec<Item>>)> {
+    lists.into_iter()
+        .filter_map(|(key, meta)| {
+            let path = list_path(snapshot_path, &key);
+            let items = fs::read_to_string(&path)
+                .context(format!("Failed to read cached item list: {:?}", path))
+                .and_then(|contents| serde_json::from_str::<Vec<Item>>(&contents)
+                    .context(format!("Failed to parse cached item list: {:?}", path)));
+
+            match items {
+                Ok(items) => Some((key, CacheEntry { data: items, timestamp: meta.timestamp, validators: meta.validators })),
+                Err(e) => {
+                    warn!("Dropping cached item list {}: {:#}", key, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Files to write and to delete for one snapshot write.
+struct SnapshotFiles {
+    writes: Vec<(PathBuf, String)>,
+    removals: Vec<PathBuf>,
+}
+
+// Serializes the snapshot and the changed full item lists, and collects the
+// files of evicted lists
+fn snapshot_files(
+    path: &Path,
+    items: &LruMap<Item>,
+    searches: &LruMap<Vec<Item>>,
+    dirty_lists: &HashSet<String>,
+    removed_lists: &HashSet<String>,
+) -> Result<SnapshotFiles> {
+    #[derive(Serialize)]
+    struct SnapshotRef<'a> {
+        version: u32,
+        items: HashMap<&'a String, &'a CacheEntry<Item>>,
+        searches: HashMap<&'a String, &'a CacheEntry<Vec<Item>>>,
+        lists: HashMap<&'a String, ListMeta>,
+    }
+
+    let mut files = Vec::new();
+    let mut snapshot = SnapshotRef {
+        version: ITEM_SCHEMA_VERSION,
+        items: items.snapshot(),
+        searches: HashMap::new(),
+        lists: HashMap::new(),
+    };
+
+    for (key, entry) in searches.snapshot() {
+        if !is_all_items_key(key) {
+            snapshot.searches.insert(key, entry);
+            continue;
+        }
+
+        if dirty_lists.contains(key) {
+            files.push((list_path(path, key), serde_json::to_string(&entry.data)?));
+        }
+        snapshot.lists.insert(key, ListMeta {
+            timestamp: entry.timestamp,
+            validators: entry.validators.clone(),
+        });
+    }
+
+    // The snapshot goes last so it never points at a list file not written yet
+    files.push((path.to_path_buf(), serde_json::to_string(&snapshot)?));
+
+    // A list cached again since its eviction keeps its file
+    let removals = removed_lists.iter()
+        .filter(|key| !snapshot.lists.contains_key(key))
+        .map(|key| list_path(path, key))
+        .collect();
+    Ok(SnapshotFiles { writes: files, removals })
+}
+
+fn write_files(files: SnapshotFiles) -> Result<()> {
+    for (path, contents) in files.writes {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create cache directory: {:?}", parent))?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated file
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents)
+            .context(format!("Failed to write cache file: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &path)?;
+    }
+
+    // After the snapshot, which no longer points at them
+    for path in files.removals {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).context(format!("Failed to remove cached item list: {:?}", path));
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(uid: &str, price: i32) -> Item {
+        serde_json::from_value(json!({ "uid": uid, "price": price })).unwrap()
+    }
+
+    fn settings(max_entries: usize) -> CacheSettings {
+        CacheSettings {
+            max_entries,
+            ..CacheSettings::default()
+        }
+    }
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("tarkov-price-overlay-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("tarkov-market.json")
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used_key() {
+        let mut map = LruMap::new(2);
+        map.insert("a".to_string(), CacheEntry::new(1));
+        map.insert("b".to_string(), CacheEntry::new(2));
+        // Reading "a" makes "b" the oldest
+        assert!(map.get("a").is_some());
+
+        assert_eq!(map.insert("c".to_string(), CacheEntry::new(3)), ["b"]);
+        assert!(map.get("a").is_some());
+        assert!(map.get("b").is_none());
+        assert_eq!(map.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn inserts_beyond_capacity_are_counted_as_evictions() {
+        let cache = PriceCache::in_memory(settings(2));
+        for uid in ["a", "b", "c"] {
+            cache.insert_item(format!("pvp:en:{}", uid), item(uid, 1)).await;
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 1);
+        assert!(cache.item("pvp:en:a").await.is_none());
+        assert_eq!(cache.item("pvp:en:c").await.map(|item| item.price), Some(1));
+    }
+
+    #[tokio::test]
+    async fn snapshot_survives_a_reload() {
+        let path = snapshot_path("reload");
+        {
+            let cache = PriceCache::persistent(path.clone(), settings(10));
+            cache.insert_item("pvp:en:a".to_string(), item("a", 100)).await;
+            cache.insert_search("pvp:en:search:chain".to_string(), vec![item("b", 200)]).await;
+            cache.insert_all_items("pvp:en", vec![item("a", 100), item("b", 200)]).await;
+        }
+
+        let cache = PriceCache::persistent(path.clone(), settings(10));
+        assert_eq!(cache.item("pvp:en:a").await.map(|item| item.price), Some(100));
+        assert_eq!(cache.search("pvp:en:search:chain").await.map(|items| items.len()), Some(1));
+        assert_eq!(cache.all_items("pvp:en").await.map(|items| items.len()), Some(2));
+        assert!(list_path(&path, "pvp:en:all_items").exists());
+    }
+
+    #[tokio::test]
+    async fn evicted_lists_lose_their_file() {
+        let path = snapshot_path("evicted-list");
+        let cache = PriceCache::persistent(path.clone(), settings(1));
+        cache.insert_all_items("pvp:en", vec![item("a", 100)]).await;
+        cache.flush().await;
+        let list_file = list_path(&path, "pvp:en:all_items");
+        assert!(list_file.exists());
+
+        // Takes the only search slot
+        cache.insert_search("pvp:en:search:chain".to_string(), vec![item("b", 200)]).await;
+        cache.lock_persist_state().last_write = None;
+        cache.flush().await;
+        assert!(!list_file.exists());
+    }
+}
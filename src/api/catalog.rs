@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::future::Future;
+use log::{info, warn};
+
+use super::{ApiError, Item, MarketMode, PriceProvider, Result, DEFAULT_LANGUAGE, SEARCH_RESULT_LIMIT};
+use super::cache::{PriceCache, stale_or_err};
+use super::names::NameIndex;
+use super::search_index::{SearchHit, SearchIndex};
+use super::single_flight::SingleFlight;
+use crate::config::Config;
+
+/// What both price clients keep besides their HTTP specifics: the cache, the
+/// market mode and language its keys are scoped to, item names in other
+/// languages and the offline search index over the full item list.
+pub(crate) struct ItemCatalog {
+    pub(crate) cache: PriceCache,
+    pub(crate) offline: bool,
+    pub(crate) language: String,
+    pub(crate) extra_languages: Vec<String>,
+    pub(crate) names: NameIndex,
+    pub(crate) search_index: SearchIndex,
+    pub(crate) market_mode: MarketMode,
+}
+
+impl ItemCatalog {
+    pub(crate) fn new(cache: PriceCache) -> Self {
+        Self {
+            cache,
+            offline: false,
+            language: DEFAULT_LANGUAGE.to_string(),
+            extra_languages: Vec::new(),
+            names: NameIndex::new(),
+            search_index: SearchIndex::new(),
+            market_mode: MarketMode::default(),
+        }
+    }
+
+    /// Honours the offline, language and market mode settings.
+    pub(crate) fn from_config(cache: PriceCache, config: &Config) -> Self {
+        Self {
+            offline: config.offline_mode,
+            language: config.display_language.clone(),
+            extra_languages: config.search_languages.clone(),
+            market_mode: config.market_mode,
+            ..Self::new(cache)
+        }
+    }
+
+    /// Switches the flea market prices are read from. The search index holds
+    /// the other mode's prices until the next refresh, so it's dropped.
+    pub(crate) fn set_market_mode(&mut self, market_mode: MarketMode) {
+        if self.market_mode != market_mode {
+            self.search_index.clear();
+        }
+        self.market_mode = market_mode;
+    }
+
+    // Cache keys are scoped so results of different market modes and
+    // languages never mix
+    pub(crate) fn cache_scope(&self) -> String {
+        self.language_scope(&self.language)
+    }
+
+    pub(crate) fn language_scope(&self, language: &str) -> String {
+        format!("{}:{}", self.market_mode.as_str(), language)
+    }
+
+    pub(crate) fn scoped_key(&self, key: &str) -> String {
+        format!("{}:{}", self.cache_scope(), key)
+    }
+
+    /// Fuzzy search over the last known full item list only, never touching
+    /// the network. `annotate` adds what the client's responses lack before
+    /// the index is built.
+    pub(crate) async fn search_offline(&self, query: &str, limit: usize, annotate: impl Fn(&mut [Item])) -> Vec<SearchHit> {
+        if self.search_index.is_empty() {
+            self.build_search_index(annotate).await;
+        }
+        self.search_index.search(query, limit)
+    }
+
+    // Builds the search index from the cached item list when no refresh has
+    // done so yet
+    async fn build_search_index(&self, annotate: impl Fn(&mut [Item])) {
+        let scope = self.cache_scope();
+        let items = match self.cache.all_items(&scope).await {
+            Some(items) => Some(items),
+            None => self.cache.stale_all_items(&scope).await,
+        };
+
+        if let Some(mut items) = items {
+            if !self.names.has_language(&self.language) {
+                self.names.update(&self.language, &items);
+            }
+            annotate(&mut items);
+            self.search_index.rebuild(&items);
+        }
+    }
+
+    /// Search results from the cache, or from `request` coalesced through
+    /// `requests`. Falls back to a stale cached result when it fails.
+    pub(crate) async fn fetch_search<F, Fut>(&self, query: &str, requests: &SingleFlight<Vec<Item>>, request: F) -> Result<Vec<Item>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<Item>>>,
+    {
+        let cache_key = self.scoped_key(&format!("search:{}", query.to_lowercase()));
+        if let Some(items) = self.cache.search(&cache_key).await {
+            info!("Cache hit for search: {}", query);
+            return Ok(items);
+        }
+
+        if self.offline {
+            return self.cache.stale_search(&cache_key).await
+                .ok_or_else(|| ApiError::Offline(format!("search {}", query)));
+        }
+
+        match requests.run(&cache_key, request).await {
+            Ok(items) => {
+                self.cache.insert_search(cache_key, items.clone()).await;
+                Ok(items)
+            },
+            Err(e) => stale_or_err(self.cache.stale_search(&cache_key).await, e),
+        }
+    }
+}
+
+/// Searches by name, short name and tags. Answered from the provider's local
+/// fuzzy index over the full item list when it has matches, so typos are
+/// tolerated and no request is needed; otherwise with `fetch`.
+pub(crate) async fn search_item<P, F>(provider: &P, query: &str, fetch: F) -> Result<Vec<Item>>
+where
+    P: PriceProvider,
+    F: Future<Output = Result<Vec<Item>>>,
+{
+    let hits = provider.search_offline(query, SEARCH_RESULT_LIMIT).await;
+    if !hits.is_empty() {
+        info!("Local index match for search: {}", query);
+        return Ok(current_prices(provider, hits).await);
+    }
+
+    fetch.await
+}
+
+// The index keeps the prices of the item list it was built from, so the
+// hits are looked up again. Hits that can't be are returned as stale.
+async fn current_prices<P: PriceProvider>(provider: &P, hits: Vec<SearchHit>) -> Vec<Item> {
+    let uids: Vec<String> = hits.iter().map(|hit| hit.item.uid.clone()).collect();
+    let mut current = provider.get_items_by_uids(&uids).await.unwrap_or_else(|e| {
+        warn!("Failed to look up current prices of search results: {}", e);
+        HashMap::new()
+    });
+
+    hits.into_iter()
+        .map(|hit| current.remove(&hit.item.uid).unwrap_or(Item { stale: true, ..hit.item }))
+        .collect()
+}
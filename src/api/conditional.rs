@@ -0,0 +1,51 @@
+use reqwest::header::{HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+
+/// HTTP cache validators of a response, sent back with the next request for
+/// the same resource so the server can answer 304 Not Modified.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Validators {
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+}
+
+impl Validators {
+    /// `None` when the server sent neither an ETag nor a Last-Modified date.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let validators = Self {
+            etag: header_value(headers, ETAG),
+            last_modified: header_value(headers, LAST_MODIFIED),
+        };
+        if validators.etag.is_none() && validators.last_modified.is_none() {
+            return None;
+        }
+        Some(validators)
+    }
+
+    /// Makes the request conditional. Servers prefer the ETag when both are
+    /// sent.
+    pub(crate) fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Outcome of a request that may have been made conditional.
+pub(crate) enum Conditional<T> {
+    /// A full response with the validators to send next time
+    Modified(T, Option<Validators>),
+    /// 304, the cached copy is still current
+    NotModified,
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use super::Item;
+
+// Uids of the currency items, whose prices give the exchange rates
+pub const DOLLARS_UID: &str = "5696686a4bdc2da3298b456a";
+pub const EUROS_UID: &str = "569668774bdc2da2298b4568";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Currency {
+    #[default]
+    #[serde(rename = "RUB", alias = "₽", alias = "rub")]
+    Rub,
+    #[serde(rename = "USD", alias = "$", alias = "usd")]
+    Usd,
+    #[serde(rename = "EUR", alias = "€", alias = "eur")]
+    Eur,
+}
+
+impl Currency {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Rub => "₽",
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+        }
+    }
+
+    /// Amount with thousand separators and the currency symbol the way the
+    /// game writes it: "33,000₽", "$420", "€300".
+    pub fn format(&self, amount: i64) -> String {
+        let digits = amount.unsigned_abs().to_string();
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+
+        let sign = if amount < 0 { "-" } else { "" };
+        match self {
+            Currency::Rub => format!("{}{}{}", sign, grouped, self.symbol()),
+            Currency::Usd | Currency::Eur => format!("{}{}{}", sign, self.symbol(), grouped),
+        }
+    }
+}
+
+/// Rouble value of one dollar and one euro.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExchangeRates {
+    pub usd: f64,
+    pub eur: f64,
+}
+
+impl Default for ExchangeRates {
+    // Trader rates at the time of writing, used until the provider's are known
+    fn default() -> Self {
+        Self {
+            usd: 139.0,
+            eur: 153.0,
+        }
+    }
+}
+
+impl ExchangeRates {
+    /// Rates from the currency items in a price list, keeping the default
+    /// for a currency that's missing.
+    pub fn from_items<'a, I: IntoIterator<Item = &'a Item>>(items: I) -> Self {
+        let mut rates = Self::default();
+        for item in items {
+            // The base price is what traders sell the currency for
+            let rate = if item.base_price > 0 { item.base_price } else { item.price };
+            if rate <= 0 {
+                continue;
+            }
+
+            match item.uid.as_str() {
+                DOLLARS_UID => rates.usd = rate as f64,
+                EUROS_UID => rates.eur = rate as f64,
+                _ => {},
+            }
+        }
+        rates
+    }
+
+    pub fn rate(&self, currency: Currency) -> f64 {
+        match currency {
+            Currency::Rub => 1.0,
+            Currency::Usd => self.usd,
+            Currency::Eur => self.eur,
+        }
+    }
+
+    pub fn to_roubles(&self, amount: i32, currency: Currency) -> i32 {
+        (amount as f64 * self.rate(currency)).round() as i32
+    }
+
+    pub fn convert(&self, amount: i32, from: Currency, to: Currency) -> i32 {
+        if from == to {
+            return amount;
+        }
+        (amount as f64 * self.rate(from) / self.rate(to)).round() as i32
+    }
+}
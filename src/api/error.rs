@@ -0,0 +1,170 @@
+use serde::de::DeserializeOwned;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
+pub enum ApiError {
+    #[error("Invalid API key")]
+    Unauthorized,
+    #[error("No API key configured")]
+    MissingApiKey,
+    #[error("Rate limited by the price API (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Daily API request quota exhausted")]
+    QuotaExceeded,
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Price API server error {status}: {body}")]
+    Server { status: u16, body: String },
+    #[error("Unexpected response status {status}: {body}")]
+    UnexpectedStatus { status: u16, body: String },
+    #[error("Price API returned an error: {0}")]
+    Backend(String),
+    #[error("Failed to decode response: {0}")]
+    Decode(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Offline and no cached data for {0}")]
+    Offline(String),
+    #[error("Not supported by this price provider: {0}")]
+    Unsupported(String),
+}
+
+impl ApiError {
+    /// Whether repeating the same request later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ApiError::RateLimited { .. } | ApiError::Server { .. } | ApiError::Network(_)
+        )
+    }
+
+    /// Whether the API couldn't be reached or didn't answer properly, as
+    /// opposed to answering with a definitive error such as a bad key. Only
+    /// these errors fall back to stale cached data.
+    pub fn is_unavailable(&self) -> bool {
+        self.is_retryable() || matches!(self, ApiError::QuotaExceeded)
+    }
+
+    /// Short message suitable for displaying on the overlay.
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "Invalid API key",
+            ApiError::MissingApiKey => "Enter an API key in the source settings",
+            ApiError::RateLimited { .. } => "Price API rate limit reached",
+            ApiError::QuotaExceeded => "Daily API quota used up",
+            ApiError::NotFound(_) => "Item not found",
+            ApiError::Server { .. } | ApiError::UnexpectedStatus { .. } | ApiError::Backend(_) => "Price API error",
+            ApiError::Decode(_) => "Unexpected price API response",
+            ApiError::Network(_) => "Price API unreachable",
+            ApiError::Offline(_) => "Offline, no cached prices",
+            ApiError::Unsupported(_) => "Not supported by the price API",
+        }
+    }
+}
+
+// Kept as a string so the error can be cloned and shared between coalesced requests
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        ApiError::Network(e.to_string())
+    }
+}
+
+/// Maps a non-success HTTP status to the matching `ApiError`.
+pub(crate) async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, ApiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, SystemTime::now()));
+    let body = response.text().await.unwrap_or_default();
+
+    Err(match status.as_u16() {
+        401 | 403 => ApiError::Unauthorized,
+        404 => ApiError::NotFound(body),
+        429 => ApiError::RateLimited { retry_after },
+        500..=599 => ApiError::Server { status: status.as_u16(), body },
+        _ => ApiError::UnexpectedStatus { status: status.as_u16(), body },
+    })
+}
+
+/// Retry-After as either delay-seconds ("120") or an HTTP date ("Wed, 21
+/// Oct 2015 07:28:00 GMT"), relative to `now`. A date in the past means
+/// retrying right away.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = parse_http_date(value)?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+// IMF-fixdate, the only date format servers may send since RFC 7231
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let (_weekday, date) = value.split_once(", ")?;
+    let parts: Vec<&str> = date.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    let time: Vec<u64> = time.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let [hour, minute, second] = time[..] else {
+        return None;
+    };
+
+    // Days since the epoch from a civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hour * 3600 + minute * 60 + second))
+}
+
+/// Reads the response body and decodes it as JSON, keeping decode failures
+/// separate from network failures.
+pub(crate) async fn decode_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ApiError> {
+    let bytes = response.bytes().await?;
+    serde_json::from_slice(&bytes).map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_accepts_seconds() {
+        assert_eq!(parse_retry_after(" 120 ", SystemTime::now()), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn retry_after_accepts_http_dates() {
+        // Wed, 21 Oct 2015 07:28:00 GMT
+        let date = UNIX_EPOCH + Duration::from_secs(1445412480);
+        let now = date - Duration::from_secs(90);
+
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now), Some(Duration::from_secs(90)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", date + Duration::from_secs(5)), Some(Duration::ZERO));
+        assert_eq!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(951782400)));
+    }
+
+    #[test]
+    fn retry_after_rejects_garbage() {
+        assert_eq!(parse_retry_after("soon", SystemTime::now()), None);
+        assert_eq!(parse_retry_after("Wed, 21 Foo 2015 07:28:00 GMT", SystemTime::now()), None);
+    }
+}
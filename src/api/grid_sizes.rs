@@ -0,0 +1,59 @@
+use anyhow::{Result, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use log::info;
+use super::Item;
+
+const GRID_SIZES_FILE: &str = "item_grid_sizes.json";
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct GridSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Offline item grid sizes for backends that don't report them (Tarkov
+/// Market), read from `item_grid_sizes.json` in the data directory:
+///
+/// ```json
+/// { "5734758f24597738025ee253": { "width": 1, "height": 1 } }
+/// ```
+#[derive(Debug, Default)]
+pub struct GridSizes {
+    sizes: HashMap<String, GridSize>,
+}
+
+impl GridSizes {
+    /// Loads the grid sizes file, or an empty set if there is none.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let path = data_dir.as_ref().join(GRID_SIZES_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .context(format!("Failed to read item grid sizes: {:?}", path))?;
+        let sizes: HashMap<String, GridSize> = serde_json::from_str(&contents)
+            .context(format!("Failed to parse item grid sizes: {:?}", path))?;
+
+        info!("Loaded grid sizes for {} items", sizes.len());
+        Ok(Self { sizes })
+    }
+
+    pub fn get(&self, uid: &str) -> Option<GridSize> {
+        self.sizes.get(uid).copied()
+    }
+
+    /// Fills in the grid size of items the backend didn't report one for.
+    pub fn apply(&self, item: &mut Item) {
+        if item.width > 0 && item.height > 0 {
+            return;
+        }
+        if let Some(size) = self.get(&item.uid) {
+            item.width = size.width;
+            item.height = size.height;
+        }
+    }
+}
@@ -1,164 +1,770 @@
-use anyhow::{Result, Context};
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::time::{Duration, Instant};
-use log::{info, warn, error};
+use std::future::Future;
+use log::{info, warn};
 
+use crate::config::Config;
+
+mod ammo;
+mod backend;
+mod cache;
+mod catalog;
+mod conditional;
+mod currency;
+mod error;
+mod grid_sizes;
+mod names;
+mod rate_limit;
+mod recipes;
+mod retry;
+mod schema;
+pub(crate) mod search_index;
+mod single_flight;
+mod tarkov_dev;
+
+pub use ammo::{AmmoStats, AmmoTable};
+pub use backend::{PriceBackend, ProviderKind};
+use cache::{PriceCache, stale_or_err};
+use catalog::ItemCatalog;
+use conditional::{Conditional, Validators};
+pub use cache::{CacheSettings, CacheStats};
+pub use currency::{Currency, ExchangeRates};
+use currency::{DOLLARS_UID, EUROS_UID};
+use error::{check_status, decode_json};
+use single_flight::SingleFlight;
+pub use error::ApiError;
+pub use grid_sizes::{GridSize, GridSizes};
+pub use rate_limit::RateLimiter;
+pub use recipes::{ItemQuantity, Recipe, RecipeKind};
+pub use search_index::SearchHit;
+pub use retry::RetryPolicy;
+pub use schema::ITEM_SCHEMA_VERSION;
+use schema::{lenient_number, null_as_default, LenientVec};
+pub use tarkov_dev::TarkovDevAPI;
+
+pub type Result<T> = std::result::Result<T, ApiError>;
+
+/// An item with its prices.
+///
+/// Deserialization is lenient so API changes don't break whole responses:
+/// everything but `uid` is optional, `null` reads as the default, numbers may
+/// come as floats or strings, both snake_case and camelCase names are
+/// accepted and fields this version doesn't know are kept in `extra`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Item {
     pub uid: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
+    #[serde(default, alias = "shortName", deserialize_with = "null_as_default")]
     pub short_name: String,
+    #[serde(default, deserialize_with = "lenient_number")]
     pub price: i32,
+    #[serde(default, alias = "avg24hPrice", deserialize_with = "lenient_number")]
     pub avg_24h_price: i32,
+    #[serde(default, alias = "avg7daysPrice", deserialize_with = "lenient_number")]
     pub avg_7days_price: i32,
+    /// Base price the flea market fee is computed from (0 when unknown)
+    #[serde(default, alias = "basePrice", deserialize_with = "lenient_number")]
+    pub base_price: i32,
+    #[serde(default, alias = "traderName", deserialize_with = "null_as_default")]
     pub trader_name: String,
+    /// Best trader price, in `trader_currency`
+    #[serde(default, alias = "traderPrice", deserialize_with = "lenient_number")]
     pub trader_price: i32,
+    #[serde(default, alias = "trader_price_cur", alias = "traderPriceCur", deserialize_with = "null_as_default")]
+    pub trader_currency: Currency,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub icon: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub img: String,
+    #[serde(default, alias = "imgBig", deserialize_with = "null_as_default")]
     pub img_big: String,
+    #[serde(default, alias = "bsgId", deserialize_with = "null_as_default")]
     pub bsg_id: String,
+    #[serde(default, alias = "isFunctional", deserialize_with = "null_as_default")]
     pub is_functional: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub updated: String,
+    /// Inventory grid width in slots (0 when the backend doesn't report it)
+    #[serde(default, deserialize_with = "lenient_number")]
+    pub width: u32,
+    /// Inventory grid height in slots (0 when the backend doesn't report it)
+    #[serde(default, deserialize_with = "lenient_number")]
+    pub height: u32,
+    /// Weight in kilograms
+    #[serde(default, deserialize_with = "lenient_number")]
+    pub weight: f32,
+    /// Every trader buy offer, not just the best one in `trader_name`/`trader_price`
+    #[serde(default, alias = "traderOffers", deserialize_with = "null_as_default")]
+    pub trader_offers: Vec<TraderOffer>,
+    /// Whether the item cannot be listed on the flea market
+    #[serde(default, alias = "bannedOnFlea", deserialize_with = "null_as_default")]
+    pub banned_on_flea: bool,
+    /// Ballistics of ammo items
+    #[serde(default)]
+    pub ammo: Option<AmmoStats>,
+    /// Set when the item was served from an expired cache entry because the
+    /// backend was unreachable or offline mode is enabled
+    #[serde(skip)]
+    pub stale: bool,
+    /// Names in other languages, keyed by language code
+    #[serde(default, alias = "localizedNames", deserialize_with = "null_as_default")]
+    pub localized_names: HashMap<String, LocalizedName>,
+    /// Fields the API sent that this version doesn't know, kept so they
+    /// survive the cache snapshot
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug)]
-struct CacheEntry<T> {
-    data: T,
-    timestamp: Instant,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TraderOffer {
+    #[serde(alias = "traderName")]
+    pub trader_name: String,
+    /// Price in `currency`
+    #[serde(deserialize_with = "lenient_number")]
+    pub price: i32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub currency: Currency,
 }
 
-impl<T> CacheEntry<T> {
-    fn new(data: T) -> Self {
-        Self {
-            data,
-            timestamp: Instant::now(),
+impl TraderOffer {
+    pub fn price_rub(&self, rates: &ExchangeRates) -> i32 {
+        rates.to_roubles(self.price, self.currency)
+    }
+}
+
+/// Which flea market prices come from. The PvE market is a separate economy
+/// with very different prices.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketMode {
+    #[default]
+    Pvp,
+    Pve,
+}
+
+impl MarketMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarketMode::Pvp => "pvp",
+            MarketMode::Pve => "pve",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MarketMode::Pvp => "PvP",
+            MarketMode::Pve => "PvE",
         }
     }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LocalizedName {
+    pub name: String,
+    pub short_name: String,
+}
+
+impl Item {
+    /// Name in the given language, falling back to the name the item was
+    /// fetched with.
+    pub fn display_name(&self, language: &str) -> &str {
+        self.localized_names.get(language)
+            .map(|localized| localized.name.as_str())
+            .unwrap_or(&self.name)
+    }
+
+    pub fn display_short_name(&self, language: &str) -> &str {
+        self.localized_names.get(language)
+            .map(|localized| localized.short_name.as_str())
+            .unwrap_or(&self.short_name)
+    }
 
-    fn is_expired(&self, ttl: Duration) -> bool {
-        self.timestamp.elapsed() > ttl
+    /// Inventory slots the item occupies, 1 when the grid size is unknown.
+    pub fn slots(&self) -> u32 {
+        (self.width * self.height).max(1)
+    }
+
+    pub fn price_per_slot(&self) -> i32 {
+        self.price / self.slots() as i32
+    }
+
+    /// Best trader price converted to roubles, for comparing with flea prices.
+    pub fn trader_price_rub(&self, rates: &ExchangeRates) -> i32 {
+        rates.to_roubles(self.trader_price, self.trader_currency)
+    }
+
+    pub fn is_ammo(&self) -> bool {
+        self.ammo.is_some() || self.tags.iter().any(|tag| tag.eq_ignore_ascii_case("ammo"))
     }
 }
 
+/// A source of item prices.
+///
+/// The template downloader and the overlay only talk to this trait, so any
+/// market backend can be swapped in without touching them.
+pub trait PriceProvider: Send + Sync {
+    /// Human readable name of the backend, used in logs and the UI.
+    fn name(&self) -> &str;
+
+    fn search_item(&self, query: &str) -> impl Future<Output = Result<Vec<Item>>> + Send;
+
+    fn get_item_by_uid(&self, uid: &str) -> impl Future<Output = Result<Item>> + Send;
+
+    fn get_all_items(&self) -> impl Future<Output = Result<Vec<Item>>> + Send;
+
+    /// Looks up several items at once, keyed by uid. Unknown uids are left out
+    /// of the result. Backends should override this to batch their requests.
+    fn get_items_by_uids(&self, uids: &[String]) -> impl Future<Output = Result<HashMap<String, Item>>> + Send {
+        async move {
+            let results = join_all(uids.iter().map(|uid| self.get_item_by_uid(uid))).await;
+            collect_lookups(uids.iter().cloned().zip(results))
+        }
+    }
+
+    /// Fetches the full item list even if the cached copy hasn't expired yet.
+    /// Used by the background refresh scheduler.
+    fn refresh_all_items(&self) -> impl Future<Output = Result<Vec<Item>>> + Send {
+        self.get_all_items()
+    }
+
+    /// Requests left in the backend's daily quota, if it has one.
+    fn remaining_quota(&self) -> Option<u32> {
+        None
+    }
+
+    /// Market whose prices are returned.
+    fn market_mode(&self) -> MarketMode {
+        MarketMode::default()
+    }
+
+    /// Fuzzy search over the locally indexed item list, without network
+    /// requests. Empty for backends without a local index.
+    fn search_offline(&self, _query: &str, _limit: usize) -> impl Future<Output = Vec<SearchHit>> + Send {
+        async { Vec::new() }
+    }
+
+    /// Rouble value of dollars and euros, from the prices of the currency
+    /// items. Goes through `get_items_by_uids`, so backends serve it from
+    /// their cache.
+    fn get_exchange_rates(&self) -> impl Future<Output = Result<ExchangeRates>> + Send {
+        async move {
+            let uids = [DOLLARS_UID.to_string(), EUROS_UID.to_string()];
+            let items = self.get_items_by_uids(&uids).await?;
+            Ok(ExchangeRates::from_items(items.values()))
+        }
+    }
+
+    /// Hideout crafts and trader barters, for backends that know them.
+    fn get_recipes(&self) -> impl Future<Output = Result<Vec<Recipe>>> + Send {
+        let name = self.name().to_string();
+        async move { Err(ApiError::Unsupported(format!("{} has no craft and barter data", name))) }
+    }
+}
+
+/// Root of the Tarkov Market API, see `Config::api_base_url`.
+pub const DEFAULT_BASE_URL: &str = "https://api.tarkov-market.app/api/v1";
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+// Single-flight key of the full item list
+const ALL_ITEMS_CACHE_KEY: &str = "all_items";
+
+// Maximum number of locally answered search results
+const SEARCH_RESULT_LIMIT: usize = 20;
+
+// Above this many cache misses one `/items/all` request is cheaper than
+// looking up every item on its own
+const BULK_LOOKUP_THRESHOLD: usize = 10;
+
 pub struct TarkovMarketAPI {
     api_key: String,
+    base_url: String,
     client: reqwest::Client,
-    item_cache: Arc<RwLock<HashMap<String, CacheEntry<Item>>>>,
-    search_cache: Arc<RwLock<HashMap<String, CacheEntry<Vec<Item>>>>>,
-    last_request: Arc<RwLock<Instant>>,
-    min_request_interval: Duration,
+    catalog: ItemCatalog,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    item_requests: SingleFlight<Item>,
+    list_requests: SingleFlight<Vec<Item>>,
+    grid_sizes: GridSizes,
+    ammo: AmmoTable,
 }
 
 impl TarkovMarketAPI {
     pub fn new(api_key: String) -> Self {
         Self {
             api_key,
+            base_url: DEFAULT_BASE_URL.to_string(),
             client: reqwest::Client::new(),
-            item_cache: Arc::new(RwLock::new(HashMap::new())),
-            search_cache: Arc::new(RwLock::new(HashMap::new())),
-            last_request: Arc::new(RwLock::new(Instant::now())),
-            min_request_interval: Duration::from_millis(200), // 5 requests per second max
+            catalog: ItemCatalog::new(PriceCache::in_memory(CacheSettings::default())),
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: RateLimiter::new(5, 5.0), // 5 requests per second max
+            item_requests: SingleFlight::new(),
+            list_requests: SingleFlight::new(),
+            grid_sizes: GridSizes::default(),
+            ammo: AmmoTable::default(),
         }
     }
 
-    async fn wait_for_rate_limit(&self) {
-        let mut last_request = self.last_request.write().await;
-        let elapsed = last_request.elapsed();
-        if elapsed < self.min_request_interval {
-            tokio::time::sleep(self.min_request_interval - elapsed).await;
+    /// Creates a client whose cache and quota usage are persisted under
+    /// `Config::data_dir` and that honours the offline, rate limit, language
+    /// and market mode settings.
+    pub fn from_config(config: &Config) -> Self {
+        let mut rate_limiter = RateLimiter::new(config.rate_limit_burst, config.rate_limit_per_second as f64);
+        if config.daily_request_quota > 0 {
+            rate_limiter = rate_limiter.with_daily_quota(
+                config.daily_request_quota,
+                Some(config.data_dir.join("api-quota.json")),
+            );
+        }
+
+        Self {
+            catalog: ItemCatalog::from_config(
+                PriceCache::persistent(config.cache_dir().join("tarkov-market.json"), CacheSettings::from_config(config)),
+                config,
+            ),
+            rate_limiter,
+            grid_sizes: GridSizes::load(&config.data_dir).unwrap_or_else(|e| {
+                warn!("Item grid sizes unavailable: {:?}", e);
+                GridSizes::default()
+            }),
+            ammo: AmmoTable::load(&config.data_dir).unwrap_or_else(|e| {
+                warn!("Ammo stats unavailable: {:?}", e);
+                AmmoTable::default()
+            }),
+            ..Self::new(config.api_key.clone()).with_base_url(&config.api_base_url)
         }
-        *last_request = Instant::now();
     }
 
-    async fn make_request<T: for<'de> Deserialize<'de>>(&self, endpoint: &str, query: &str) -> Result<T> {
-        self.wait_for_rate_limit().await;
+    /// Points the client at a different API root, e.g. the local mock server.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        let url = format!("https://api.tarkov-market.app/api/v1{}?{}", endpoint, query);
-        let response = self.client
-            .get(&url)
-            .header("x-api-key", &self.api_key)
-            .send()
-            .await
-            .context("Failed to send request")?;
+    /// In offline mode no requests are made and the last cached snapshot is
+    /// served, flagged as stale.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.catalog.offline = offline;
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Sets the language item names are fetched in, plus additional languages
+    /// whose names are indexed for searching.
+    pub fn set_languages(&mut self, display_language: &str, extra_languages: Vec<String>) {
+        self.catalog.language = display_language.to_string();
+        self.catalog.extra_languages = extra_languages;
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("API request failed with status {}: {}", status, body));
+    /// Switches the flea market prices are read from, see
+    /// `ItemCatalog::set_market_mode`.
+    pub fn set_market_mode(&mut self, market_mode: MarketMode) {
+        self.catalog.set_market_mode(market_mode);
+    }
+
+    pub fn market_mode(&self) -> MarketMode {
+        self.catalog.market_mode
+    }
+
+    /// Grid sizes used for items the API doesn't report a size for.
+    pub fn set_grid_sizes(&mut self, grid_sizes: GridSizes) {
+        self.grid_sizes = grid_sizes;
+    }
+
+    /// Ammo stats attached to ammo items, which the API doesn't report.
+    pub fn set_ammo_table(&mut self, ammo: AmmoTable) {
+        self.ammo = ammo;
+    }
+
+    pub fn remaining_quota(&self) -> Option<u32> {
+        self.rate_limiter.remaining_quota()
+    }
+
+    /// Number of ammo types with offline stats, see `set_ammo_table`.
+    pub fn ammo_types(&self) -> usize {
+        self.ammo.len()
+    }
+
+    // Adds what the API responses lack: names in other languages, grid sizes
+    // and ammo stats
+    fn annotate(&self, item: &mut Item) {
+        self.catalog.names.localize(item);
+        self.grid_sizes.apply(item);
+        self.ammo.apply(item);
+    }
+
+    fn annotate_all(&self, items: &mut [Item]) {
+        for item in items {
+            self.annotate(item);
+        }
+    }
+
+    fn query_string(&self, query: &str, language: &str) -> String {
+        let lang = format!("lang={}", market_language_code(language));
+        if query.is_empty() {
+            lang
+        } else {
+            format!("{}&{}", query, lang)
         }
+    }
 
-        response.json().await.context("Failed to parse response")
+    async fn make_request<T: DeserializeOwned>(&self, endpoint: &str, query: &str) -> Result<T> {
+        match self.make_conditional_request(endpoint, query, None).await? {
+            Conditional::Modified(data, _) => Ok(data),
+            // Servers only answer 304 to conditional requests
+            Conditional::NotModified => Err(ApiError::UnexpectedStatus { status: 304, body: String::new() }),
+        }
     }
 
-    pub async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
-        let cache_key = query.to_lowercase();
-        {
-            let cache = self.search_cache.read().await;
-            if let Some(entry) = cache.get(&cache_key) {
-                if !entry.is_expired(Duration::from_secs(300)) { // 5 minute cache
-                    info!("Cache hit for search: {}", query);
-                    return Ok(entry.data.clone());
-                }
+    /// Like `make_request`, but with `If-None-Match`/`If-Modified-Since`
+    /// headers when validators are given.
+    async fn make_conditional_request<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &str,
+        validators: Option<&Validators>,
+    ) -> Result<Conditional<T>> {
+        // PvE prices live under their own path
+        let mode_prefix = match self.catalog.market_mode {
+            MarketMode::Pvp => "",
+            MarketMode::Pve => "/pve",
+        };
+        let url = &format!("{}{}{}?{}", self.base_url, mode_prefix, endpoint, query);
+        self.retry_policy.run(move || async move {
+            self.rate_limiter.acquire().await?;
+
+            let mut request = self.client
+                .get(url)
+                .header("x-api-key", &self.api_key);
+            if let Some(validators) = validators {
+                request = validators.apply(request);
+            }
+            let response = request.send().await?;
+
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                return Ok(Conditional::NotModified);
             }
+            let response = check_status(response).await?;
+            let validators = Validators::from_headers(response.headers());
+            Ok(Conditional::Modified(decode_json(response).await?, validators))
+        }).await
+    }
+
+    // Item list endpoints, skipping malformed items instead of failing
+    async fn request_items(&self, endpoint: &str, query: &str) -> Result<Vec<Item>> {
+        let items: LenientVec<Item> = self.make_request(endpoint, query).await?;
+        Ok(items.0)
+    }
+
+    // Downloads the item list of a cache scope and caches it with its
+    // validators. When the cached list has validators the request is
+    // conditional, and a 304 just marks the cached list as fresh again.
+    async fn request_all_items(&self, scope: &str, query: &str) -> Result<Vec<Item>> {
+        let validators = self.catalog.cache.all_items_validators(scope).await;
+        let mut response = self.make_conditional_request::<LenientVec<Item>>("/items/all", query, validators.as_ref()).await?;
+
+        if let Conditional::NotModified = response {
+            if let Some(items) = self.catalog.cache.revalidate_all_items(scope).await {
+                info!("Item list for {} not modified, cache refreshed", scope);
+                return Ok(items);
+            }
+            // The cached list was evicted since, download it in full
+            response = self.make_conditional_request("/items/all", query, None).await?;
         }
 
-        let items: Vec<Item> = self.make_request("/item", &format!("q={}", query)).await?;
-        
-        let mut cache = self.search_cache.write().await;
-        cache.insert(cache_key, CacheEntry::new(items.clone()));
-        
-        Ok(items)
+        match response {
+            Conditional::Modified(items, validators) => {
+                self.catalog.cache.insert_validated_all_items(scope, items.0.clone(), validators).await;
+                Ok(items.0)
+            },
+            Conditional::NotModified => Err(ApiError::UnexpectedStatus { status: 304, body: String::new() }),
+        }
+    }
+
+    /// Searches by name, short name and tags, see `catalog::search_item`.
+    /// Misses of the local index are queried in the display language.
+    pub async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
+        catalog::search_item(self, query, async {
+            let query_string = self.query_string(&format!("q={}", query), &self.catalog.language);
+            let mut items = self.catalog.fetch_search(query, &self.list_requests, || {
+                self.request_items("/item", &query_string)
+            }).await?;
+            self.annotate_all(&mut items);
+            Ok(items)
+        }).await
+    }
+
+    /// Fuzzy search over the last known full item list only, never touching
+    /// the network.
+    pub async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.catalog.search_offline(query, limit, |items| self.annotate_all(items)).await
     }
 
     pub async fn get_item_by_uid(&self, uid: &str) -> Result<Item> {
-        {
-            let cache = self.item_cache.read().await;
-            if let Some(entry) = cache.get(uid) {
-                if !entry.is_expired(Duration::from_secs(300)) { // 5 minute cache
-                    info!("Cache hit for item: {}", uid);
-                    return Ok(entry.data.clone());
+        let mut item = self.fetch_item(uid).await?;
+        self.annotate(&mut item);
+        Ok(item)
+    }
+
+    async fn fetch_item(&self, uid: &str) -> Result<Item> {
+        let cache_key = self.catalog.scoped_key(uid);
+        if let Some(item) = self.catalog.cache.item(&cache_key).await {
+            info!("Cache hit for item: {}", uid);
+            return Ok(item);
+        }
+
+        if self.catalog.offline {
+            return self.catalog.cache.stale_item(&cache_key).await
+                .ok_or_else(|| ApiError::Offline(format!("item {}", uid)));
+        }
+
+        let result = self.item_requests.run(&cache_key, || async {
+            let query_string = self.query_string(&format!("uid={}", uid), &self.catalog.language);
+            let mut items = self.request_items("/item", &query_string).await?;
+            items.pop().ok_or_else(|| ApiError::NotFound(uid.to_string()))
+        }).await;
+        match result {
+            Ok(item) => {
+                self.catalog.cache.insert_item(cache_key, item.clone()).await;
+                Ok(item)
+            },
+            Err(e) => stale_or_err(self.catalog.cache.stale_item(&cache_key).await, e),
+        }
+    }
+
+    /// Looks up several items at once. Cached items are served directly, a
+    /// fresh full item list is used when available, and the remaining misses
+    /// are fetched either one by one or, if there are many, with a single
+    /// `/items/all` request. A failed bulk request falls back to the single
+    /// lookups, so the cache hits are still returned.
+    pub async fn get_items_by_uids(&self, uids: &[String]) -> Result<HashMap<String, Item>> {
+        let mut found = HashMap::new();
+        let mut misses = Vec::new();
+
+        for uid in uids {
+            if found.contains_key(uid) || misses.contains(uid) {
+                continue;
+            }
+            match self.catalog.cache.item(&self.catalog.scoped_key(uid)).await {
+                Some(item) => {
+                    found.insert(uid.clone(), item);
+                },
+                None => misses.push(uid.clone()),
+            }
+        }
+
+        if !misses.is_empty() {
+            let all_items = match self.catalog.cache.all_items(&self.catalog.cache_scope()).await {
+                Some(all_items) => Some(all_items),
+                None if misses.len() > BULK_LOOKUP_THRESHOLD && !self.catalog.offline => match self.fetch_all_items().await {
+                    Ok(all_items) => Some(all_items),
+                    Err(e) => {
+                        warn!("Bulk lookup failed, fetching {} items one by one: {}", misses.len(), e);
+                        None
+                    },
+                },
+                None => None,
+            };
+
+            if let Some(all_items) = all_items {
+                let hits: Vec<Item> = all_items.into_iter()
+                    .filter(|item| misses.contains(&item.uid))
+                    .collect();
+                self.catalog.cache.insert_items(&self.catalog.cache_scope(), hits.clone()).await;
+                found.extend(hits.into_iter().map(|item| (item.uid.clone(), item)));
+            } else {
+                // The rate limiter still spaces out the concurrent requests
+                let results = join_all(misses.iter().map(|uid| self.fetch_item(uid))).await;
+                match collect_lookups(misses.into_iter().zip(results)) {
+                    Ok(items) => found.extend(items),
+                    Err(e) if found.is_empty() => return Err(e),
+                    Err(_) => {},
                 }
             }
         }
 
-        let mut items: Vec<Item> = self.make_request("/item", &format!("uid={}", uid)).await?;
-        let item = items.pop().ok_or_else(|| anyhow::anyhow!("Item not found"))?;
-        
-        let mut cache = self.item_cache.write().await;
-        cache.insert(uid.to_string(), CacheEntry::new(item.clone()));
-        
-        Ok(item)
+        for item in found.values_mut() {
+            self.annotate(item);
+        }
+        Ok(found)
     }
 
     pub async fn get_all_items(&self) -> Result<Vec<Item>> {
-        const CACHE_KEY: &str = "all_items";
-        {
-            let cache = self.search_cache.read().await;
-            if let Some(entry) = cache.get(CACHE_KEY) {
-                if !entry.is_expired(Duration::from_secs(3600)) { // 1 hour cache
-                    info!("Cache hit for all items");
-                    return Ok(entry.data.clone());
-                }
+        let mut items = self.fetch_all_items().await?;
+        self.annotate_all(&mut items);
+        if self.catalog.search_index.is_empty() {
+            self.catalog.search_index.rebuild(&items);
+        }
+        Ok(items)
+    }
+
+    async fn fetch_all_items(&self) -> Result<Vec<Item>> {
+        if let Some(items) = self.catalog.cache.all_items(&self.catalog.cache_scope()).await {
+            info!("Cache hit for all items");
+            if !self.catalog.names.has_language(&self.catalog.language) {
+                self.catalog.names.update(&self.catalog.language, &items);
             }
+            return Ok(items);
         }
 
-        let items: Vec<Item> = self.make_request("/items/all", "").await?;
-        
-        let mut cache = self.search_cache.write().await;
-        cache.insert(CACHE_KEY.to_string(), CacheEntry::new(items.clone()));
-        
+        self.fetch_all_items_uncached().await
+    }
+
+    /// Fetches the full item list bypassing the cache freshness check, and
+    /// refreshes the names of the extra languages once they're older than
+    /// `NAMES_REFRESH_INTERVAL`.
+    pub async fn refresh_all_items(&self) -> Result<Vec<Item>> {
+        let mut items = self.fetch_all_items_uncached().await?;
+        for language in &self.catalog.extra_languages {
+            if !self.catalog.names.needs_refresh(language) {
+                continue;
+            }
+            if let Err(e) = self.refresh_names(language).await {
+                warn!("Failed to refresh {} item names: {}", language, e);
+            }
+        }
+
+        self.annotate_all(&mut items);
+        self.catalog.search_index.rebuild(&items);
+        Ok(items)
+    }
+
+    async fn fetch_all_items_uncached(&self) -> Result<Vec<Item>> {
+        let scope = self.catalog.cache_scope();
+        let items = if self.catalog.offline {
+            self.catalog.cache.stale_all_items(&scope).await
+                .ok_or_else(|| ApiError::Offline("item list".to_string()))?
+        } else {
+            let query_string = self.query_string("", &self.catalog.language);
+            let result = self.list_requests.run(&self.catalog.scoped_key(ALL_ITEMS_CACHE_KEY), || {
+                self.request_all_items(&scope, &query_string)
+            }).await;
+            match result {
+                Ok(items) => items,
+                Err(e) => stale_or_err(self.catalog.cache.stale_all_items(&scope).await, e)?,
+            }
+        };
+
+        self.catalog.names.update(&self.catalog.language, &items);
         Ok(items)
     }
 
+    /// Fetches the item list in another language to index its names. Names
+    /// rarely change, so any cached copy is used when offline or on errors.
+    async fn refresh_names(&self, language: &str) -> Result<()> {
+        let scope = self.catalog.language_scope(language);
+        let items = if self.catalog.offline {
+            self.catalog.cache.stale_all_items(&scope).await
+                .ok_or_else(|| ApiError::Offline(format!("{} item names", language)))?
+        } else {
+            let query_string = self.query_string("", language);
+            let result = self.list_requests.run(&format!("{}:{}", scope, ALL_ITEMS_CACHE_KEY), || {
+                self.request_all_items(&scope, &query_string)
+            }).await;
+            match result {
+                Ok(items) => items,
+                Err(e) => stale_or_err(self.catalog.cache.stale_all_items(&scope).await, e)?,
+            }
+        };
+
+        self.catalog.names.update(language, &items);
+        Ok(())
+    }
+
     pub async fn clear_cache(&self) {
-        let mut item_cache = self.item_cache.write().await;
-        let mut search_cache = self.search_cache.write().await;
-        item_cache.clear();
-        search_cache.clear();
+        self.catalog.cache.clear().await;
         info!("Cache cleared");
     }
-} 
\ No newline at end of file
+
+    /// Drops long expired cache entries, see `CacheSettings::stale_retention`.
+    pub async fn evict_expired(&self) -> usize {
+        self.catalog.cache.evict_expired().await
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.catalog.cache.stats()
+    }
+}
+
+/// Collects the results of single item lookups. Unknown items are left out
+/// and other failures are logged and skipped, so one bad uid doesn't fail
+/// the others; only when every lookup failed is the first error returned.
+fn collect_lookups<I>(results: I) -> Result<HashMap<String, Item>>
+where
+    I: IntoIterator<Item = (String, Result<Item>)>,
+{
+    let mut items = HashMap::new();
+    let mut first_error = None;
+    for (uid, result) in results {
+        match result {
+            Ok(item) => {
+                items.insert(uid, item);
+            },
+            Err(ApiError::NotFound(_)) => {},
+            Err(e) => {
+                warn!("Failed to look up item {}: {}", uid, e);
+                first_error.get_or_insert(e);
+            },
+        }
+    }
+
+    match first_error {
+        Some(e) if items.is_empty() => Err(e),
+        _ => Ok(items),
+    }
+}
+
+// Tarkov Market uses its own codes for a few languages
+fn market_language_code(language: &str) -> &str {
+    match language {
+        "zh" => "cn",
+        "cs" => "cz",
+        "ja" => "jp",
+        other => other,
+    }
+}
+
+impl PriceProvider for TarkovMarketAPI {
+    fn name(&self) -> &str {
+        "Tarkov Market"
+    }
+
+    async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
+        TarkovMarketAPI::search_item(self, query).await
+    }
+
+    async fn get_item_by_uid(&self, uid: &str) -> Result<Item> {
+        TarkovMarketAPI::get_item_by_uid(self, uid).await
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<Item>> {
+        TarkovMarketAPI::get_all_items(self).await
+    }
+
+    async fn get_items_by_uids(&self, uids: &[String]) -> Result<HashMap<String, Item>> {
+        TarkovMarketAPI::get_items_by_uids(self, uids).await
+    }
+
+    async fn refresh_all_items(&self) -> Result<Vec<Item>> {
+        TarkovMarketAPI::refresh_all_items(self).await
+    }
+
+    fn remaining_quota(&self) -> Option<u32> {
+        TarkovMarketAPI::remaining_quota(self)
+    }
+
+    fn market_mode(&self) -> MarketMode {
+        self.catalog.market_mode
+    }
+
+    async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        TarkovMarketAPI::search_offline(self, query, limit).await
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use super::{Item, LocalizedName};
+
+// Names rarely change, extra languages are refetched at most this often
+pub(crate) const NAMES_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Item names per language, built from full item lists fetched in each
+/// configured language and attached to items so they can be displayed and
+/// searched in any of them.
+pub(crate) struct NameIndex {
+    // language -> uid -> name
+    names: RwLock<HashMap<String, HashMap<String, LocalizedName>>>,
+    // language -> when its names were last replaced
+    updated_at: RwLock<HashMap<String, Instant>>,
+}
+
+impl NameIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            names: RwLock::new(HashMap::new()),
+            updated_at: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the names of `language` with the ones of the given item list.
+    pub(crate) fn update(&self, language: &str, items: &[Item]) {
+        let names = items.iter()
+            .map(|item| {
+                (item.uid.clone(), LocalizedName {
+                    name: item.name.clone(),
+                    short_name: item.short_name.clone(),
+                })
+            })
+            .collect();
+        self.names.write().unwrap().insert(language.to_string(), names);
+        self.updated_at.write().unwrap().insert(language.to_string(), Instant::now());
+    }
+
+    /// Whether the names of `language` are missing or older than
+    /// `NAMES_REFRESH_INTERVAL`.
+    pub(crate) fn needs_refresh(&self, language: &str) -> bool {
+        self.updated_at.read().unwrap()
+            .get(language)
+            .map_or(true, |updated_at| updated_at.elapsed() >= NAMES_REFRESH_INTERVAL)
+    }
+
+    pub(crate) fn has_language(&self, language: &str) -> bool {
+        self.names.read().unwrap().contains_key(language)
+    }
+
+    /// Attaches every known translation to the item.
+    pub(crate) fn localize(&self, item: &mut Item) {
+        let names = self.names.read().unwrap();
+        for (language, language_names) in names.iter() {
+            if let Some(name) = language_names.get(&item.uid) {
+                item.localized_names.insert(language.clone(), name.clone());
+            }
+        }
+    }
+
+    pub(crate) fn localize_all(&self, items: &mut [Item]) {
+        for item in items {
+            self.localize(item);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use log::warn;
+
+use super::ApiError;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Requests between two saves of the usage counter. A crash loses at most
+// this many, the counter is also saved on the first request of a day and
+// when the limiter is dropped.
+const SAVE_EVERY_REQUESTS: u32 = 10;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct QuotaUsage {
+    /// UTC day (days since the unix epoch) the usage counter belongs to
+    day: u64,
+    used: u32,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+    quota: QuotaUsage,
+    // Requests since the counter was last saved, and the day it was saved for
+    unsaved: u32,
+    saved_day: u64,
+}
+
+impl BucketState {
+    fn save_due(&self) -> bool {
+        self.unsaved >= SAVE_EVERY_REQUESTS || self.saved_day != self.quota.day
+    }
+}
+
+/// Token bucket rate limiter with an optional daily request quota.
+///
+/// Requests take one token each; tokens refill continuously up to `capacity`,
+/// which allows short bursts while keeping the long-term rate bounded. The
+/// lock is never held while waiting, so any number of requests can be in
+/// flight at once.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    daily_quota: Option<u32>,
+    quota_path: Option<PathBuf>,
+    state: Mutex<BucketState>,
+    // Keeps saves in order, each writes the latest counter
+    save_lock: tokio::sync::Mutex<()>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            refill_per_sec: refill_per_sec.max(f64::MIN_POSITIVE),
+            daily_quota: None,
+            quota_path: None,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+                quota: QuotaUsage { day: current_day(), used: 0 },
+                unsaved: 0,
+                saved_day: current_day(),
+            }),
+            save_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Limits the number of requests per UTC day. When `quota_path` is given
+    /// the usage counter is loaded from and saved to that file so restarts
+    /// don't reset it.
+    pub fn with_daily_quota(mut self, quota: u32, quota_path: Option<PathBuf>) -> Self {
+        if let Some(path) = &quota_path {
+            match load_usage(path) {
+                Ok(usage) => {
+                    let state = self.state.get_mut().unwrap();
+                    state.saved_day = usage.day;
+                    state.quota = usage;
+                },
+                Err(e) => warn!("Starting with a fresh API quota counter: {}", e),
+            }
+        }
+
+        self.daily_quota = Some(quota);
+        self.quota_path = quota_path;
+        self
+    }
+
+    /// Waits until a token is available and takes it, or fails right away if
+    /// the daily quota is used up.
+    pub async fn acquire(&self) -> Result<(), ApiError> {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                self.refill(&mut state);
+
+                if let Some(quota) = self.daily_quota {
+                    if state.quota.used >= quota {
+                        return Err(ApiError::QuotaExceeded);
+                    }
+                }
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    state.quota.used += 1;
+                    state.unsaved += 1;
+                    None
+                } else {
+                    Some(Duration::from_secs_f64((1.0 - state.tokens) / self.refill_per_sec))
+                }
+            };
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => {
+                    self.save_usage().await;
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    /// Requests left today, or `None` when no daily quota is configured.
+    pub fn remaining_quota(&self) -> Option<u32> {
+        let quota = self.daily_quota?;
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state);
+        Some(quota.saturating_sub(state.quota.used))
+    }
+
+    fn refill(&self, state: &mut BucketState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        state.last_refill = now;
+
+        let today = current_day();
+        if state.quota.day != today {
+            state.quota = QuotaUsage { day: today, used: 0 };
+        }
+    }
+
+    // Saves the usage counter on the blocking thread pool when a day
+    // started or enough requests were made since the last save. Most
+    // requests return before touching the save lock.
+    async fn save_usage(&self) {
+        let Some(path) = &self.quota_path else {
+            return;
+        };
+        if !self.state.lock().unwrap().save_due() {
+            return;
+        }
+
+        let _saving = self.save_lock.lock().await;
+        let usage = {
+            // A save that held the lock before may have written it already
+            let mut state = self.state.lock().unwrap();
+            if !state.save_due() {
+                return;
+            }
+            state.unsaved = 0;
+            state.saved_day = state.quota.day;
+            state.quota.clone()
+        };
+
+        let path = path.clone();
+        let result = tokio::task::spawn_blocking(move || save_usage(&path, &usage))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        if let Err(e) = result {
+            warn!("Failed to save API quota usage: {:#}", e);
+        }
+    }
+}
+
+// Writes the unsaved requests. Inside a runtime the write goes to the
+// blocking thread pool so dropping a backend never stalls a worker thread.
+impl Drop for RateLimiter {
+    fn drop(&mut self) {
+        let Some(path) = self.quota_path.take() else {
+            return;
+        };
+
+        let state = self.state.get_mut().unwrap();
+        if state.unsaved == 0 {
+            return;
+        }
+
+        let usage = state.quota.clone();
+        let save = move || {
+            if let Err(e) = save_usage(&path, &usage) {
+                warn!("Failed to save API quota usage: {:#}", e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(save);
+            },
+            Err(_) => save(),
+        }
+    }
+}
+
+fn current_day() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
+
+fn load_usage(path: &PathBuf) -> anyhow::Result<QuotaUsage> {
+    let usage_str = fs::read_to_string(path)
+        .context(format!("Failed to read quota file: {:?}", path))?;
+    serde_json::from_str(&usage_str)
+        .context(format!("Failed to parse quota file: {:?}", path))
+}
+
+fn save_usage(path: &PathBuf, usage: &QuotaUsage) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string(usage)?)
+        .context(format!("Failed to write quota file: {:?}", tmp_path))?;
+    fs::rename(&tmp_path, path)
+        .context(format!("Failed to replace quota file: {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quota_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("tarkov-price-overlay-{}", std::process::id()))
+            .join(format!("{}.json", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn bucket_allows_a_burst_then_waits() {
+        let limiter = RateLimiter::new(2, 0.01);
+
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+        // The next token is 100 seconds away
+        let third = tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await;
+        assert!(third.is_err());
+    }
+
+    #[tokio::test]
+    async fn bucket_refills_over_time() {
+        let limiter = RateLimiter::new(1, 50.0);
+
+        limiter.acquire().await.unwrap();
+        let second = tokio::time::timeout(Duration::from_secs(1), limiter.acquire()).await;
+        assert!(matches!(second, Ok(Ok(()))));
+    }
+
+    #[tokio::test]
+    async fn quota_is_enforced() {
+        let limiter = RateLimiter::new(10, 10.0).with_daily_quota(2, None);
+
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+        assert_eq!(limiter.remaining_quota(), Some(0));
+        assert!(matches!(limiter.acquire().await, Err(ApiError::QuotaExceeded)));
+    }
+
+    #[test]
+    fn quota_resets_on_a_new_day() {
+        let limiter = RateLimiter::new(10, 10.0).with_daily_quota(5, None);
+        {
+            let mut state = limiter.state.lock().unwrap();
+            state.quota = QuotaUsage { day: current_day() - 1, used: 5 };
+        }
+
+        assert_eq!(limiter.remaining_quota(), Some(5));
+    }
+
+    #[test]
+    fn quota_survives_a_restart() {
+        let path = quota_path("restart");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let limiter = RateLimiter::new(10, 10.0).with_daily_quota(5, Some(path.clone()));
+        runtime.block_on(async {
+            limiter.acquire().await.unwrap();
+            limiter.acquire().await.unwrap();
+        });
+        // Outside the runtime the drop saves right away
+        drop(limiter);
+
+        let limiter = RateLimiter::new(10, 10.0).with_daily_quota(5, Some(path.clone()));
+        assert_eq!(limiter.remaining_quota(), Some(3));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn usage_from_an_earlier_day_is_ignored() {
+        let path = quota_path("earlier-day");
+        save_usage(&path, &QuotaUsage { day: current_day() - 1, used: 5 }).unwrap();
+
+        let limiter = RateLimiter::new(10, 10.0).with_daily_quota(5, Some(path.clone()));
+        assert_eq!(limiter.remaining_quota(), Some(5));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemQuantity {
+    pub uid: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecipeKind {
+    /// Hideout craft at a station of the given level
+    Craft {
+        station: String,
+        level: u32,
+        duration_secs: u64,
+    },
+    /// Trader barter at the given loyalty level
+    Barter {
+        trader: String,
+        level: u32,
+    },
+}
+
+/// A hideout craft or trader barter turning `inputs` into `outputs`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Recipe {
+    pub id: String,
+    pub kind: RecipeKind,
+    pub inputs: Vec<ItemQuantity>,
+    pub outputs: Vec<ItemQuantity>,
+}
+
+impl Recipe {
+    /// "Workbench 2" or "Prapor LL1"
+    pub fn source(&self) -> String {
+        match &self.kind {
+            RecipeKind::Craft { station, level, .. } => format!("{} {}", station, level),
+            RecipeKind::Barter { trader, level } => format!("{} LL{}", trader, level),
+        }
+    }
+}
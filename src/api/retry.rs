@@ -0,0 +1,88 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use log::warn;
+
+use super::{ApiError, Result};
+
+/// Retry behaviour for transient API failures (rate limits, 5xx, network).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if e.is_retryable() && attempt < self.max_retries && !self.exceeds_max_delay(&e) => {
+                    let delay = self.delay_for(attempt, &e);
+                    attempt += 1;
+                    warn!("Request failed ({}), retrying in {:?} (attempt {}/{})", e, delay, attempt, self.max_retries);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Delay before the given retry attempt. A server provided Retry-After
+    /// wins (capped at `max_delay`), otherwise exponential backoff with
+    /// jitter is used so concurrent clients don't retry in lockstep.
+    pub fn delay_for(&self, attempt: u32, error: &ApiError) -> Duration {
+        if let ApiError::RateLimited { retry_after: Some(retry_after) } = error {
+            return (*retry_after).min(self.max_delay);
+        }
+
+        let backoff = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        // Equal jitter: half fixed, half random
+        backoff / 2 + backoff.mul_f64(random_fraction() / 2.0)
+    }
+
+    // A Retry-After longer than we're willing to wait fails the request right
+    // away instead, the error still carries it for the caller
+    fn exceeds_max_delay(&self, error: &ApiError) -> bool {
+        match error {
+            ApiError::RateLimited { retry_after: Some(retry_after) } => *retry_after > self.max_delay,
+            _ => false,
+        }
+    }
+}
+
+// Random number in [0, 1) without pulling in an RNG crate. std seeds the
+// RandomState keys randomly once per thread and only increments them for
+// every new RandomState, but the SipHash output still differs enough for
+// jitter.
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,98 @@
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::Value;
+use log::warn;
+
+/// Version of the `Item` schema. Bumped when fields change meaning, so
+/// snapshots written by a newer version aren't misread by an older one.
+/// Older snapshots are read with the same lenient rules as API responses.
+pub const ITEM_SCHEMA_VERSION: u32 = 2;
+
+/// A JSON array decoded entry by entry. Entries that fail to decode are
+/// logged and skipped instead of failing the whole response.
+#[derive(Debug)]
+pub(crate) struct LenientVec<T>(pub Vec<T>);
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for LenientVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<Value>::deserialize(deserializer)?;
+        let total = values.len();
+
+        let entries: Vec<T> = values.into_iter()
+            .enumerate()
+            .filter_map(|(index, value)| {
+                let id = entry_id(&value);
+                match serde_json::from_value(value) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Skipping malformed entry {} ({}): {}", index, id, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        if entries.len() < total {
+            warn!("Skipped {} malformed entries out of {}", total - entries.len(), total);
+        }
+        Ok(LenientVec(entries))
+    }
+}
+
+// Identifies an entry in logs by its uid or id when it has one
+fn entry_id(value: &Value) -> String {
+    ["uid", "id", "bsg_id", "bsgId"].iter()
+        .find_map(|key| value.get(key).and_then(Value::as_str))
+        .unwrap_or("no id")
+        .to_string()
+}
+
+/// Treats `null` like a missing field.
+pub(crate) fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Reads a number that may also come as a float, a numeric string or
+/// `null` (0), as the Tarkov Market API does for some fields.
+pub(crate) fn lenient_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromF64,
+{
+    let number = match Value::deserialize(deserializer)? {
+        Value::Null => 0.0,
+        Value::Number(number) => number.as_f64().unwrap_or(0.0),
+        Value::String(text) if text.trim().is_empty() => 0.0,
+        Value::String(text) => text.trim().parse().map_err(|_| {
+            serde::de::Error::custom(format!("invalid number: {:?}", text))
+        })?,
+        other => return Err(serde::de::Error::custom(format!("expected a number, got {}", other))),
+    };
+    Ok(T::from_f64(number))
+}
+
+pub(crate) trait FromF64 {
+    fn from_f64(value: f64) -> Self;
+}
+
+impl FromF64 for i32 {
+    fn from_f64(value: f64) -> Self {
+        value.round() as i32
+    }
+}
+
+impl FromF64 for u32 {
+    fn from_f64(value: f64) -> Self {
+        value.round().max(0.0) as u32
+    }
+}
+
+impl FromF64 for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
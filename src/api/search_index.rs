@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use super::Item;
+
+// Results scoring below this are dropped, low enough to tolerate a typo or two
+const MIN_SCORE: f32 = 0.35;
+
+// Relative weight of matches per field
+const NAME_WEIGHT: f32 = 1.0;
+const LOCALIZED_NAME_WEIGHT: f32 = 0.9;
+const TAG_WEIGHT: f32 = 0.5;
+
+// Added when the query equals a short name exactly, so "LEDX" ranks the LEDX
+// above items that merely contain it
+const EXACT_SHORT_NAME_BONUS: f32 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub item: Item,
+    pub score: f32,
+}
+
+#[derive(Default)]
+struct IndexData {
+    items: Vec<Item>,
+    // Per item: (weight, number of distinct trigrams) of every indexed field
+    fields: Vec<Vec<(f32, usize)>>,
+    // Trigram -> (item index, field index)
+    postings: HashMap<String, Vec<(usize, usize)>>,
+}
+
+/// Fuzzy trigram index over the full item list, searching names (in every
+/// known language), short names and tags. Rebuilt from each item list
+/// refresh so searches work instantly and offline.
+pub(crate) struct SearchIndex {
+    data: RwLock<IndexData>,
+}
+
+impl SearchIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: RwLock::new(IndexData::default()),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.read().unwrap().items.is_empty()
+    }
+
+    /// Drops every indexed item until the next rebuild.
+    pub(crate) fn clear(&self) {
+        *self.data.write().unwrap() = IndexData::default();
+    }
+
+    pub(crate) fn rebuild(&self, items: &[Item]) {
+        let mut data = IndexData::default();
+
+        for (index, item) in items.iter().enumerate() {
+            let mut texts = vec![(NAME_WEIGHT, item.name.as_str()), (NAME_WEIGHT, item.short_name.as_str())];
+            for localized in item.localized_names.values() {
+                texts.push((LOCALIZED_NAME_WEIGHT, localized.name.as_str()));
+                texts.push((LOCALIZED_NAME_WEIGHT, localized.short_name.as_str()));
+            }
+            for tag in &item.tags {
+                texts.push((TAG_WEIGHT, tag.as_str()));
+            }
+
+            let mut fields = Vec::with_capacity(texts.len());
+            for (weight, text) in texts {
+                let grams = trigrams(text);
+                if grams.is_empty() {
+                    continue;
+                }
+
+                let field = fields.len();
+                fields.push((weight, grams.len()));
+                for gram in grams {
+                    data.postings.entry(gram).or_default().push((index, field));
+                }
+            }
+
+            data.fields.push(fields);
+        }
+
+        data.items = items.to_vec();
+        *self.data.write().unwrap() = data;
+    }
+
+    /// Best matches first, at most `limit`.
+    pub(crate) fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query_grams = trigrams(query);
+        if query_grams.is_empty() {
+            return Vec::new();
+        }
+
+        let data = self.data.read().unwrap();
+
+        // Shared trigrams per (item, field)
+        let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+        for gram in &query_grams {
+            if let Some(postings) = data.postings.get(gram) {
+                for posting in postings {
+                    *shared.entry(*posting).or_default() += 1;
+                }
+            }
+        }
+
+        // Best field score per item
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for ((index, field), count) in shared {
+            let (weight, field_grams) = data.fields[index][field];
+            // Mostly how much of the query the field contains, with a share
+            // of overall similarity so closer matches rank higher
+            let containment = count as f32 / query_grams.len() as f32;
+            let dice = 2.0 * count as f32 / (query_grams.len() + field_grams) as f32;
+            let score = weight * (0.7 * containment + 0.3 * dice);
+
+            let best = scores.entry(index).or_default();
+            if score > *best {
+                *best = score;
+            }
+        }
+
+        let normalized_query = normalize(query);
+        let mut hits: Vec<SearchHit> = scores.into_iter()
+            .filter(|(_, score)| *score >= MIN_SCORE)
+            .map(|(index, score)| {
+                let item = &data.items[index];
+                let bonus = if normalize(&item.short_name) == normalized_query {
+                    EXACT_SHORT_NAME_BONUS
+                } else {
+                    0.0
+                };
+                SearchHit {
+                    item: item.clone(),
+                    score: score + bonus,
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score.total_cmp(&a.score)
+                .then_with(|| a.item.name.len().cmp(&b.item.name.len()))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+// Lowercase words of letters and digits separated by single spaces
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Distinct trigrams of every word padded with a space on both sides, so short
+// words and word starts still produce trigrams
+fn trigrams(text: &str) -> HashSet<String> {
+    let mut grams = HashSet::new();
+    for word in normalize(text).split(' ').filter(|word| !word.is_empty()) {
+        let padded: Vec<char> = format!(" {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            grams.insert(window.iter().collect());
+        }
+    }
+    grams
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Items the index is tested with, also searched by the tests of its users.
+    pub(crate) fn items() -> Vec<Item> {
+        serde_json::from_value(json!([
+            {
+                "uid": "5c0530ee86f774697952d952",
+                "name": "LEDX Skin Transilluminator",
+                "short_name": "LEDX",
+                "tags": ["Medical supplies"],
+            },
+            {
+                "uid": "5c052e6986f7746b207bc3c9",
+                "name": "Portable defibrillator for the LEDX lab",
+                "short_name": "Defib",
+                "tags": ["Medical supplies"],
+            },
+            {
+                "uid": "5734758f24597738025ee253",
+                "name": "Golden neck chain",
+                "short_name": "Chain",
+                "tags": ["Barter", "Valuables"],
+                "localized_names": {
+                    "ru": { "name": "Золотая цепочка", "short_name": "Цепь" },
+                },
+            },
+        ])).unwrap()
+    }
+
+    fn index() -> SearchIndex {
+        let index = SearchIndex::new();
+        index.rebuild(&items());
+        index
+    }
+
+    fn uids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.item.uid.as_str()).collect()
+    }
+
+    #[test]
+    fn exact_short_name_ranks_first() {
+        let hits = index().search("ledx", 10);
+        assert_eq!(uids(&hits), ["5c0530ee86f774697952d952", "5c052e6986f7746b207bc3c9"]);
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    #[test]
+    fn tolerates_typos() {
+        let hits = index().search("golden nekc chian", 10);
+        assert_eq!(uids(&hits), ["5734758f24597738025ee253"]);
+    }
+
+    #[test]
+    fn matches_localized_names_and_tags() {
+        let index = index();
+        assert_eq!(uids(&index.search("золотая", 10)), ["5734758f24597738025ee253"]);
+        assert_eq!(uids(&index.search("valuables", 10)), ["5734758f24597738025ee253"]);
+    }
+
+    #[test]
+    fn drops_weak_matches_and_respects_the_limit() {
+        let index = index();
+        assert!(index.search("bitcoin", 10).is_empty());
+        assert!(index.search(" ,. ", 10).is_empty());
+        assert_eq!(index.search("ledx", 1).len(), 1);
+    }
+
+    #[test]
+    fn clear_empties_the_index() {
+        let index = index();
+        index.clear();
+        assert!(index.is_empty());
+        assert!(index.search("ledx", 10).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+use super::ApiError;
+
+type InFlight<T> = Mutex<HashMap<String, broadcast::Sender<Result<T, ApiError>>>>;
+
+/// Coalesces concurrent requests for the same key: the first caller performs
+/// the request and every caller arriving while it's in flight gets a copy of
+/// its result instead of hitting the network again.
+pub(crate) struct SingleFlight<T> {
+    in_flight: InFlight<T>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) async fn run<F, Fut>(&self, key: &str, fetch: F) -> Result<T, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let waiter = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    let (sender, _) = broadcast::channel(1);
+                    in_flight.insert(key.to_string(), sender);
+                    None
+                }
+            }
+        };
+
+        if let Some(mut receiver) = waiter {
+            return receiver.recv().await.unwrap_or_else(|_| {
+                Err(ApiError::Backend("Coalesced request was cancelled".to_string()))
+            });
+        }
+
+        // Removes the entry if the leading request is cancelled, which closes
+        // the channel and releases the waiters
+        let mut guard = InFlightGuard { in_flight: &self.in_flight, key: Some(key) };
+        let result = fetch().await;
+
+        guard.key = None;
+        let sender = self.in_flight.lock().unwrap().remove(key);
+        if let Some(sender) = sender {
+            let _ = sender.send(result.clone());
+        }
+
+        result
+    }
+}
+
+struct InFlightGuard<'a, T> {
+    in_flight: &'a InFlight<T>,
+    key: Option<&'a str>,
+}
+
+impl<T> Drop for InFlightGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.in_flight.lock().unwrap().remove(key);
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use log::{info, warn};
+
+use super::{AmmoStats, ApiError, CacheSettings, CacheStats, Currency, Item, ItemQuantity, MarketMode, PriceProvider, Recipe, RecipeKind, Result, RetryPolicy, TraderOffer, ALL_ITEMS_CACHE_KEY};
+use super::cache::{PriceCache, stale_or_err};
+use super::catalog::{self, ItemCatalog};
+use super::error::{check_status, decode_json};
+use super::search_index::SearchHit;
+use super::schema::LenientVec;
+use super::single_flight::SingleFlight;
+use crate::config::Config;
+
+const DEFAULT_ENDPOINT: &str = "https://api.tarkov.dev/graphql";
+
+// Vendor name tarkov.dev uses for flea market entries in `sellFor`
+const FLEA_MARKET_VENDOR: &str = "flea-market";
+
+const ITEMS_BY_ID_QUERY: &str = "query ItemsById($ids: [ID], $lang: LanguageCode, $gameMode: GameMode) { items(ids: $ids, lang: $lang, gameMode: $gameMode) { ...ItemFields } }";
+
+const SEARCH_QUERY: &str = "query SearchItems($name: String, $lang: LanguageCode, $gameMode: GameMode) { items(name: $name, lang: $lang, gameMode: $gameMode) { ...ItemFields } }";
+
+const ALL_ITEMS_QUERY: &str = "query AllItems($lang: LanguageCode, $gameMode: GameMode) { items(lang: $lang, gameMode: $gameMode) { ...ItemFields } }";
+
+const ITEM_FIELDS: &str = r#"
+fragment ItemFields on Item {
+    id
+    name
+    shortName
+    avg24hPrice
+    lastLowPrice
+    basePrice
+    width
+    height
+    weight
+    types
+    updated
+    iconLink
+    gridImageLink
+    image512pxLink
+    properties {
+        ... on ItemPropertiesAmmo {
+            caliber
+            damage
+            penetrationPower
+            armorDamage
+            projectileCount
+        }
+    }
+    sellFor {
+        price
+        currency
+        priceRUB
+        vendor {
+            name
+            normalizedName
+        }
+    }
+}
+"#;
+
+const RECIPES_QUERY: &str = r#"
+query Recipes($gameMode: GameMode) {
+    crafts(gameMode: $gameMode) {
+        id
+        station { name }
+        level
+        duration
+        requiredItems { item { id } count }
+        rewardItems { item { id } count }
+    }
+    barters(gameMode: $gameMode) {
+        id
+        trader { name }
+        level
+        requiredItems { item { id } count }
+        rewardItems { item { id } count }
+    }
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQLError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemsData {
+    // Removed items come back as null, malformed ones are skipped
+    items: LenientVec<Option<DevItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevItem {
+    id: String,
+    name: String,
+    short_name: Option<String>,
+    avg24h_price: Option<i32>,
+    last_low_price: Option<i32>,
+    base_price: Option<i32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    weight: Option<f32>,
+    #[serde(default)]
+    types: Vec<String>,
+    updated: Option<String>,
+    icon_link: Option<String>,
+    grid_image_link: Option<String>,
+    image512px_link: Option<String>,
+    properties: Option<DevProperties>,
+    #[serde(default)]
+    sell_for: Vec<DevSellOffer>,
+}
+
+// Only the ammo fields are queried, other item kinds come back empty
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevProperties {
+    caliber: Option<String>,
+    damage: Option<f64>,
+    penetration_power: Option<f64>,
+    armor_damage: Option<f64>,
+    projectile_count: Option<u32>,
+}
+
+impl DevProperties {
+    fn into_ammo(self) -> Option<AmmoStats> {
+        Some(AmmoStats {
+            // "Caliber545x39" -> "545x39"
+            caliber: self.caliber?.trim_start_matches("Caliber").to_string(),
+            damage: self.damage?.round() as u32,
+            penetration: self.penetration_power?.round() as u32,
+            armor_damage: self.armor_damage.unwrap_or(0.0).round() as u32,
+            projectile_count: self.projectile_count.unwrap_or(1),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevSellOffer {
+    price: Option<i32>,
+    // "RUB", "USD" or "EUR"
+    currency: Option<Currency>,
+    #[serde(rename = "priceRUB")]
+    price_rub: Option<i32>,
+    vendor: DevVendor,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevVendor {
+    name: String,
+    normalized_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecipesData {
+    #[serde(default)]
+    crafts: Vec<DevCraft>,
+    #[serde(default)]
+    barters: Vec<DevBarter>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevCraft {
+    id: String,
+    station: DevNamed,
+    level: u32,
+    duration: Option<u64>,
+    required_items: Vec<DevItemCount>,
+    reward_items: Vec<DevItemCount>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevBarter {
+    id: String,
+    trader: DevNamed,
+    level: u32,
+    required_items: Vec<DevItemCount>,
+    reward_items: Vec<DevItemCount>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DevNamed {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DevItemCount {
+    item: Option<DevItemId>,
+    count: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DevItemId {
+    id: String,
+}
+
+impl DevItemCount {
+    // Entries whose item was removed from the game come back as null
+    fn into_quantity(self) -> Option<ItemQuantity> {
+        Some(ItemQuantity {
+            uid: self.item?.id,
+            count: self.count.round() as u32,
+        })
+    }
+}
+
+impl DevSellOffer {
+    fn to_trader_offer(&self) -> TraderOffer {
+        // Older responses only had the rouble price
+        let (price, currency) = match (self.price, self.currency) {
+            (Some(price), Some(currency)) => (price, currency),
+            _ => (self.price_rub.unwrap_or(0), Currency::Rub),
+        };
+
+        TraderOffer {
+            trader_name: self.vendor.name.clone(),
+            price,
+            currency,
+        }
+    }
+}
+
+impl From<DevItem> for Item {
+    fn from(item: DevItem) -> Self {
+        let trader_offers: Vec<&DevSellOffer> = item.sell_for
+            .iter()
+            .filter(|offer| offer.vendor.normalized_name != FLEA_MARKET_VENDOR)
+            .collect();
+
+        // Best by rouble value, kept in the currency the trader pays in
+        let best_offer = trader_offers.iter()
+            .max_by_key(|offer| offer.price_rub.unwrap_or(0))
+            .map(|offer| offer.to_trader_offer());
+        let trader_offers: Vec<TraderOffer> = trader_offers.iter().map(|offer| offer.to_trader_offer()).collect();
+        let avg_24h_price = item.avg24h_price.unwrap_or(0);
+
+        Self {
+            uid: item.id.clone(),
+            name: item.name,
+            short_name: item.short_name.unwrap_or_default(),
+            price: item.last_low_price.unwrap_or(avg_24h_price),
+            avg_24h_price,
+            // tarkov.dev doesn't expose a 7 day average, fall back to the 24h one
+            avg_7days_price: avg_24h_price,
+            base_price: item.base_price.unwrap_or(0),
+            trader_name: best_offer.as_ref().map(|offer| offer.trader_name.clone()).unwrap_or_default(),
+            trader_price: best_offer.as_ref().map(|offer| offer.price).unwrap_or(0),
+            trader_currency: best_offer.as_ref().map(|offer| offer.currency).unwrap_or_default(),
+            icon: item.icon_link.unwrap_or_default(),
+            img: item.grid_image_link.unwrap_or_default(),
+            img_big: item.image512px_link.unwrap_or_default(),
+            bsg_id: item.id,
+            is_functional: item.types.iter().any(|t| t == "gun"),
+            banned_on_flea: item.types.iter().any(|t| t == "noFlea"),
+            ammo: item.properties.and_then(DevProperties::into_ammo),
+            tags: item.types,
+            updated: item.updated.unwrap_or_default(),
+            width: item.width.unwrap_or(0),
+            height: item.height.unwrap_or(0),
+            weight: item.weight.unwrap_or(0.0),
+            trader_offers,
+            stale: false,
+            extra: HashMap::new(),
+            localized_names: HashMap::new(),
+        }
+    }
+}
+
+/// Price client for the community tarkov.dev GraphQL API. Unlike
+/// `TarkovMarketAPI` it doesn't need an API key.
+pub struct TarkovDevAPI {
+    endpoint: String,
+    client: reqwest::Client,
+    catalog: ItemCatalog,
+    retry_policy: RetryPolicy,
+    item_requests: SingleFlight<Item>,
+    list_requests: SingleFlight<Vec<Item>>,
+}
+
+impl TarkovDevAPI {
+    pub fn new() -> Self {
+        Self::with_endpoint(DEFAULT_ENDPOINT)
+    }
+
+    /// Creates a client talking to a different GraphQL endpoint, e.g. a local
+    /// stand-in server replaying recorded responses.
+    pub fn with_endpoint(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            client: reqwest::Client::new(),
+            catalog: ItemCatalog::new(PriceCache::in_memory(CacheSettings::default())),
+            retry_policy: RetryPolicy::default(),
+            item_requests: SingleFlight::new(),
+            list_requests: SingleFlight::new(),
+        }
+    }
+
+    /// Creates a client whose cache is persisted under `Config::data_dir` and
+    /// that honours the offline, language and market mode settings.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            catalog: ItemCatalog::from_config(
+                PriceCache::persistent(config.cache_dir().join("tarkov-dev.json"), CacheSettings::from_config(config)),
+                config,
+            ),
+            ..Self::new()
+        }
+    }
+
+    /// In offline mode no requests are made and the last cached snapshot is
+    /// served, flagged as stale.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.catalog.offline = offline;
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Sets the language item names are fetched in, plus additional languages
+    /// whose names are indexed for searching.
+    pub fn set_languages(&mut self, display_language: &str, extra_languages: Vec<String>) {
+        self.catalog.language = display_language.to_string();
+        self.catalog.extra_languages = extra_languages;
+    }
+
+    /// Switches the flea market prices are read from, see
+    /// `ItemCatalog::set_market_mode`.
+    pub fn set_market_mode(&mut self, market_mode: MarketMode) {
+        self.catalog.set_market_mode(market_mode);
+    }
+
+    /// Runs an items query, adding the `lang` and `gameMode` variables.
+    async fn query_items(&self, query: &str, mut variables: serde_json::Value, language: &str) -> Result<Vec<Item>> {
+        variables["lang"] = json!(language);
+        let data: ItemsData = self.query(&format!("{}\n{}", query, ITEM_FIELDS), variables).await?;
+        Ok(data.items.0.into_iter().flatten().map(Item::from).collect())
+    }
+
+    /// Runs a GraphQL query in the configured market mode.
+    async fn query<T: DeserializeOwned>(&self, query: &str, mut variables: serde_json::Value) -> Result<T> {
+        variables["gameMode"] = json!(match self.catalog.market_mode {
+            MarketMode::Pvp => "regular",
+            MarketMode::Pve => "pve",
+        });
+        let body = json!({
+            "query": query,
+            "variables": variables,
+        });
+
+        let body = &body;
+        let response: GraphQLResponse<T> = self.retry_policy.run(move || async move {
+            let response = self.client
+                .post(&self.endpoint)
+                .json(body)
+                .send()
+                .await?;
+
+            decode_json(check_status(response).await?).await
+        }).await?;
+
+        if let Some(error) = response.errors.first() {
+            return Err(ApiError::Backend(error.message.clone()));
+        }
+
+        response.data.ok_or_else(|| ApiError::Decode("GraphQL response has no data".to_string()))
+    }
+
+    /// Fetches every hideout craft and trader barter. Not cached, recipes
+    /// are snapshotted by `RecipeBook` instead.
+    pub async fn get_recipes(&self) -> Result<Vec<Recipe>> {
+        if self.catalog.offline {
+            return Err(ApiError::Offline("recipes".to_string()));
+        }
+
+        let data: RecipesData = self.query(RECIPES_QUERY, json!({})).await?;
+        let crafts = data.crafts.into_iter().map(|craft| Recipe {
+            id: craft.id,
+            kind: RecipeKind::Craft {
+                station: craft.station.name,
+                level: craft.level,
+                duration_secs: craft.duration.unwrap_or(0),
+            },
+            inputs: craft.required_items.into_iter().flat_map(DevItemCount::into_quantity).collect(),
+            outputs: craft.reward_items.into_iter().flat_map(DevItemCount::into_quantity).collect(),
+        });
+        let barters = data.barters.into_iter().map(|barter| Recipe {
+            id: barter.id,
+            kind: RecipeKind::Barter {
+                trader: barter.trader.name,
+                level: barter.level,
+            },
+            inputs: barter.required_items.into_iter().flat_map(DevItemCount::into_quantity).collect(),
+            outputs: barter.reward_items.into_iter().flat_map(DevItemCount::into_quantity).collect(),
+        });
+
+        Ok(crafts.chain(barters).collect())
+    }
+
+    /// Searches by name, short name and tags, see `catalog::search_item`.
+    /// Misses of the local index are queried in the display language.
+    pub async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
+        catalog::search_item(self, query, async {
+            let mut items = self.catalog.fetch_search(query, &self.list_requests, || {
+                self.query_items(SEARCH_QUERY, json!({ "name": query }), &self.catalog.language)
+            }).await?;
+            self.catalog.names.localize_all(&mut items);
+            Ok(items)
+        }).await
+    }
+
+    /// Fuzzy search over the last known full item list only, never touching
+    /// the network.
+    pub async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.catalog.search_offline(query, limit, |items| self.catalog.names.localize_all(items)).await
+    }
+
+    pub async fn get_item_by_uid(&self, uid: &str) -> Result<Item> {
+        let cache_key = self.catalog.scoped_key(uid);
+        let mut item = if let Some(item) = self.catalog.cache.item(&cache_key).await {
+            info!("Cache hit for item: {}", uid);
+            item
+        } else if self.catalog.offline {
+            self.catalog.cache.stale_item(&cache_key).await
+                .ok_or_else(|| ApiError::Offline(format!("item {}", uid)))?
+        } else {
+            let result = self.item_requests.run(&cache_key, || async {
+                let mut items = self.query_items(ITEMS_BY_ID_QUERY, json!({ "ids": [uid] }), &self.catalog.language).await?;
+                items.pop().ok_or_else(|| ApiError::NotFound(uid.to_string()))
+            }).await;
+            match result {
+                Ok(item) => {
+                    self.catalog.cache.insert_item(cache_key, item.clone()).await;
+                    item
+                },
+                Err(e) => stale_or_err(self.catalog.cache.stale_item(&cache_key).await, e)?,
+            }
+        };
+
+        self.catalog.names.localize(&mut item);
+        Ok(item)
+    }
+
+    /// Looks up several items at once. Cached items are served directly and
+    /// all misses are fetched with a single GraphQL query.
+    pub async fn get_items_by_uids(&self, uids: &[String]) -> Result<HashMap<String, Item>> {
+        let mut found = HashMap::new();
+        let mut misses = Vec::new();
+
+        for uid in uids {
+            if found.contains_key(uid) || misses.contains(uid) {
+                continue;
+            }
+            match self.catalog.cache.item(&self.catalog.scoped_key(uid)).await {
+                Some(item) => {
+                    found.insert(uid.clone(), item);
+                },
+                None => misses.push(uid.clone()),
+            }
+        }
+
+        if !misses.is_empty() {
+            let result = if self.catalog.offline {
+                Err(ApiError::Offline("batch lookup".to_string()))
+            } else {
+                self.query_items(ITEMS_BY_ID_QUERY, json!({ "ids": misses }), &self.catalog.language).await
+            };
+
+            match result {
+                Ok(items) => {
+                    self.catalog.cache.insert_items(&self.catalog.cache_scope(), items.clone()).await;
+                    found.extend(items.into_iter().map(|item| (item.uid.clone(), item)));
+                },
+                Err(e) if self.catalog.offline || e.is_unavailable() => {
+                    if !self.catalog.offline {
+                        warn!("Batch lookup failed, serving stale cached data: {}", e);
+                    }
+                    for uid in misses {
+                        if let Some(item) = self.catalog.cache.stale_item(&self.catalog.scoped_key(&uid)).await {
+                            found.insert(uid, item);
+                        }
+                    }
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        for item in found.values_mut() {
+            self.catalog.names.localize(item);
+        }
+        Ok(found)
+    }
+
+    pub async fn get_all_items(&self) -> Result<Vec<Item>> {
+        if let Some(mut items) = self.catalog.cache.all_items(&self.catalog.cache_scope()).await {
+            info!("Cache hit for all items");
+            if !self.catalog.names.has_language(&self.catalog.language) {
+                self.catalog.names.update(&self.catalog.language, &items);
+            }
+            self.catalog.names.localize_all(&mut items);
+            if self.catalog.search_index.is_empty() {
+                self.catalog.search_index.rebuild(&items);
+            }
+            return Ok(items);
+        }
+
+        self.refresh_all_items().await
+    }
+
+    /// Fetches the full item list bypassing the cache freshness check, and
+    /// refreshes the names of the extra languages once they're older than
+    /// `NAMES_REFRESH_INTERVAL`.
+    pub async fn refresh_all_items(&self) -> Result<Vec<Item>> {
+        let mut items = self.fetch_all_items(&self.catalog.language).await?;
+        self.catalog.names.update(&self.catalog.language, &items);
+
+        for language in &self.catalog.extra_languages {
+            if !self.catalog.names.needs_refresh(language) {
+                continue;
+            }
+            match self.fetch_all_items(language).await {
+                Ok(language_items) => self.catalog.names.update(language, &language_items),
+                Err(e) => warn!("Failed to refresh {} item names: {}", language, e),
+            }
+        }
+
+        self.catalog.names.localize_all(&mut items);
+        self.catalog.search_index.rebuild(&items);
+        Ok(items)
+    }
+
+    async fn fetch_all_items(&self, language: &str) -> Result<Vec<Item>> {
+        let scope = self.catalog.language_scope(language);
+        if self.catalog.offline {
+            return self.catalog.cache.stale_all_items(&scope).await
+                .ok_or_else(|| ApiError::Offline("item list".to_string()));
+        }
+
+        let result = self.list_requests.run(&format!("{}:{}", scope, ALL_ITEMS_CACHE_KEY), || {
+            self.query_items(ALL_ITEMS_QUERY, json!({}), language)
+        }).await;
+        match result {
+            Ok(items) => {
+                self.catalog.cache.insert_all_items(&scope, items.clone()).await;
+                Ok(items)
+            },
+            Err(e) => stale_or_err(self.catalog.cache.stale_all_items(&scope).await, e),
+        }
+    }
+
+    pub async fn clear_cache(&self) {
+        self.catalog.cache.clear().await;
+        info!("Cache cleared");
+    }
+
+    /// Drops long expired cache entries, see `CacheSettings::stale_retention`.
+    pub async fn evict_expired(&self) -> usize {
+        self.catalog.cache.evict_expired().await
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.catalog.cache.stats()
+    }
+}
+
+impl PriceProvider for TarkovDevAPI {
+    fn name(&self) -> &str {
+        "tarkov.dev"
+    }
+
+    async fn search_item(&self, query: &str) -> Result<Vec<Item>> {
+        TarkovDevAPI::search_item(self, query).await
+    }
+
+    async fn get_item_by_uid(&self, uid: &str) -> Result<Item> {
+        TarkovDevAPI::get_item_by_uid(self, uid).await
+    }
+
+    async fn get_all_items(&self) -> Result<Vec<Item>> {
+        TarkovDevAPI::get_all_items(self).await
+    }
+
+    async fn get_items_by_uids(&self, uids: &[String]) -> Result<HashMap<String, Item>> {
+        TarkovDevAPI::get_items_by_uids(self, uids).await
+    }
+
+    async fn refresh_all_items(&self) -> Result<Vec<Item>> {
+        TarkovDevAPI::refresh_all_items(self).await
+    }
+
+    fn market_mode(&self) -> MarketMode {
+        self.catalog.market_mode
+    }
+
+    async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        TarkovDevAPI::search_offline(self, query, limit).await
+    }
+
+    async fn get_recipes(&self) -> Result<Vec<Recipe>> {
+        TarkovDevAPI::get_recipes(self).await
+    }
+}
@@ -0,0 +1,337 @@
+//! Minimal stand-in for the Tarkov Market REST API (and the tarkov.dev GraphQL
+//! endpoint) serving fixture JSON from disk.
+//!
+//! Point `Config::api_base_url` at `http://127.0.0.1:<port>/api/v1` to use it.
+//!
+//! Routes:
+//! - `GET /api/v1/items/all`: `tarkov_market/items_all.json`, with an ETag
+//!   and Last-Modified, or 304 when the request's `If-None-Match` or
+//!   `If-Modified-Since` match
+//! - `GET /api/v1/item?uid=<uid>`: `tarkov_market/item_<uid>.json`, or `[]`
+//! - `GET /api/v1/item?q=<query>`: `tarkov_market/search.json`
+//! - `POST /graphql`: `tarkov_dev/recipes.json` for craft and barter queries,
+//!   `tarkov_dev/items.json` otherwise, filtered by the `ids` and `name`
+//!   variables
+//!
+//! PvE routes under `/api/v1/pve/` serve the same fixtures.
+//!
+//! `--fixtures tests/fixtures/recorded` serves responses recorded from the
+//! live APIs instead: camelCase fields, nulls, fields the client doesn't know
+//! and a few malformed entries the client has to skip.
+//!
+//! The `x-api-key` header selects failure modes:
+//! - `invalid`      -> 403 with an error body
+//! - `rate-limited` -> 429 with `Retry-After: 2`
+//! - `server-error` -> 500
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    api_key: String,
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, reason: &'static str, body: String) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    fn error(status: u16, reason: &'static str, message: &str) -> Self {
+        Self::json(status, reason, format!("{{\"error\":\"{}\"}}", message))
+    }
+}
+
+fn main() {
+    let mut port = 8181;
+    let mut fixtures = PathBuf::from("tests/fixtures");
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args.next()
+                    .and_then(|p| p.parse().ok())
+                    .expect("--port expects a number");
+            }
+            "--fixtures" => {
+                fixtures = PathBuf::from(args.next().expect("--fixtures expects a directory"));
+            }
+            _ => {
+                eprintln!("Usage: mock-tarkov-market [--port PORT] [--fixtures DIR]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).expect("Failed to bind mock server port");
+    println!("Mock Tarkov Market server listening on http://127.0.0.1:{}/api/v1", port);
+    println!("Serving fixtures from {}", fixtures.display());
+
+    let fixtures = Arc::new(fixtures);
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let fixtures = fixtures.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &fixtures) {
+                eprintln!("Connection error: {}", e);
+            }
+        });
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, fixtures: &Path) -> std::io::Result<()> {
+    let request = read_request(&mut stream)?;
+    let response = route(&request, fixtures);
+
+    // Logged before answering so the line is out by the time the client has
+    // the response
+    println!("{} {}?{} -> {}", request.method, request.path, request.query, response.status);
+    write_response(&mut stream, &response)
+}
+
+fn read_request(stream: &mut TcpStream) -> std::io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut api_key = String::new();
+    let mut if_none_match = None;
+    let mut if_modified_since = None;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "x-api-key" => api_key = value.trim().to_string(),
+                "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                "if-none-match" => if_none_match = Some(value.trim().to_string()),
+                "if-modified-since" => if_modified_since = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        api_key,
+        if_none_match,
+        if_modified_since,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn route(request: &Request, fixtures: &Path) -> Response {
+    match request.api_key.as_str() {
+        "invalid" => return Response::error(403, "Forbidden", "Invalid API key"),
+        "rate-limited" => {
+            let mut response = Response::error(429, "Too Many Requests", "Rate limit exceeded");
+            response.headers.push(("Retry-After", "2".to_string()));
+            return response;
+        }
+        "server-error" => return Response::error(500, "Internal Server Error", "Internal server error"),
+        _ => {}
+    }
+
+    let path = request.path.replacen("/api/v1/pve/", "/api/v1/", 1);
+    match (request.method.as_str(), path.as_str()) {
+        ("GET", "/api/v1/items/all") => conditional_fixture(fixtures, "tarkov_market/items_all.json", request),
+        ("GET", "/api/v1/item") => {
+            if let Some(uid) = query_param(&request.query, "uid") {
+                let path = format!("tarkov_market/item_{}.json", uid);
+                if fixtures.join(&path).exists() {
+                    fixture(fixtures, &path)
+                } else {
+                    Response::json(200, "OK", "[]".to_string())
+                }
+            } else if query_param(&request.query, "q").is_some() {
+                fixture(fixtures, "tarkov_market/search.json")
+            } else {
+                Response::error(400, "Bad Request", "Missing uid or q parameter")
+            }
+        }
+        ("POST", "/graphql") if request.body.contains("crafts") => fixture(fixtures, "tarkov_dev/recipes.json"),
+        ("POST", "/graphql") => graphql_items(fixtures, request),
+        _ => Response::error(404, "Not Found", "Not found"),
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn fixture(fixtures: &Path, name: &str) -> Response {
+    match fs::read_to_string(fixtures.join(name)) {
+        Ok(body) => Response::json(200, "OK", body),
+        Err(e) => Response::error(500, "Internal Server Error", &format!("Missing fixture {}: {}", name, e)),
+    }
+}
+
+// Serves the item fixture narrowed down to the items the query's `ids` or
+// `name` variables select, like the real endpoint. Entries without the
+// filtered field are dropped, unfiltered queries get the fixture verbatim.
+fn graphql_items(fixtures: &Path, request: &Request) -> Response {
+    let response = fixture(fixtures, "tarkov_dev/items.json");
+    if response.status != 200 {
+        return response;
+    }
+
+    let Ok(query) = serde_json::from_str::<Value>(&request.body) else {
+        return Response::error(400, "Bad Request", "Invalid GraphQL request");
+    };
+    let variables = &query["variables"];
+    let ids: Option<Vec<&str>> = variables["ids"].as_array()
+        .map(|ids| ids.iter().filter_map(Value::as_str).collect());
+    let name = variables["name"].as_str().map(str::to_lowercase);
+    if ids.is_none() && name.is_none() {
+        return response;
+    }
+
+    let Ok(mut data) = serde_json::from_str::<Value>(&response.body) else {
+        return response;
+    };
+    if let Some(items) = data["data"]["items"].as_array_mut() {
+        items.retain(|item| {
+            let id_matches = ids.as_ref().is_none_or(|ids| {
+                item["id"].as_str().is_some_and(|id| ids.contains(&id))
+            });
+            let name_matches = name.as_ref().is_none_or(|name| {
+                ["name", "shortName"].iter().any(|field| {
+                    item[*field].as_str().is_some_and(|value| value.to_lowercase().contains(name.as_str()))
+                })
+            });
+            id_matches && name_matches
+        });
+    }
+    Response::json(200, "OK", data.to_string())
+}
+
+// Serves a fixture with an ETag (hash of the content) and a Last-Modified date
+// (file mtime), answering 304 when the request's validators still match
+fn conditional_fixture(fixtures: &Path, name: &str, request: &Request) -> Response {
+    let path = fixtures.join(name);
+    let mut response = fixture(fixtures, name);
+    if response.status != 200 {
+        return response;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    response.body.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    let last_modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .map(http_date)
+        .ok();
+
+    // The ETag takes precedence; dates are compared verbatim since clients
+    // echo back the Last-Modified they got
+    let not_modified = match (&request.if_none_match, &request.if_modified_since) {
+        (Some(if_none_match), _) => if_none_match.split(',').any(|tag| tag.trim() == etag),
+        (None, Some(since)) => last_modified.as_ref() == Some(since),
+        (None, None) => false,
+    };
+    if not_modified {
+        response = Response::json(304, "Not Modified", String::new());
+    }
+
+    response.headers.push(("ETag", etag));
+    if let Some(last_modified) = last_modified {
+        response.headers.push(("Last-Modified", last_modified));
+    }
+    response
+}
+
+// RFC 7231 date, e.g. "Wed, 01 May 2024 12:00:00 GMT"
+fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.reason,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
@@ -1,128 +1,178 @@
 use anyhow::{Result, Context};
+use obs_sys::{
+    obs_get_source_by_name, obs_source_frame, obs_source_get_frame, obs_source_release,
+    obs_source_release_frame, video_format_VIDEO_FORMAT_BGRA, video_format_VIDEO_FORMAT_BGRX,
+    video_format_VIDEO_FORMAT_RGBA,
+};
 use opencv::{
-    core::{Mat, Point, Rect, Scalar, Vector},
+    core::{Mat, Point, Rect, CV_8UC4},
     imgproc,
     prelude::*,
 };
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use log::{info, warn, error};
-use crate::template::TemplateManager;
-
-pub struct FrameCapture {
-    width: i32,
-    height: i32,
-    last_frame: Arc<RwLock<Option<Mat>>>,
-    preprocessed_frame: Arc<RwLock<Option<Mat>>>,
-}
-
-impl FrameCapture {
-    pub fn new(width: i32, height: i32) -> Self {
-        Self {
-            width,
-            height,
-            last_frame: Arc::new(RwLock::new(None)),
-            preprocessed_frame: Arc::new(RwLock::new(None)),
-        }
+use std::ffi::{c_void, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, RwLock};
+use log::warn;
+use crate::api::PriceProvider;
+use crate::market::{FleaFeeCalculator, RecipeBook};
+use crate::overlay::{best_uses, resolve_matches, DetectionResult, Detections};
+use crate::template::{preprocess_template, TemplateManager};
+
+// Minimum time between two detections of a source. Matching every template
+// is far too slow to run each frame.
+const DETECTION_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Copies the current frame of the OBS source named `source_name` as a
+/// grayscale image, preprocessed like the templates. `None` if there is no
+/// such source, it has no frame (only async sources such as a Video Capture
+/// Device have one) or its pixel format isn't RGBA, BGRA or BGRX.
+///
+/// # Safety
+///
+/// Must be called on an OBS thread while OBS is running.
+pub unsafe fn grab_frame(source_name: &str) -> Result<Option<Mat>> {
+    let name = CString::new(source_name).context("Invalid source name")?;
+    let source = obs_get_source_by_name(name.as_ptr());
+    if source.is_null() {
+        return Ok(None);
     }
 
-    pub async fn capture_frame(&self, frame: &Mat) -> Result<()> {
-        // Store the raw frame
-        let mut last_frame = self.last_frame.write().await;
-        *last_frame = Some(frame.clone());
-
-        // Preprocess the frame for better detection
-        let mut preprocessed = frame.clone();
-        self.preprocess_frame(&mut preprocessed)?;
+    let frame = obs_source_get_frame(source);
+    let result = if frame.is_null() {
+        Ok(None)
+    } else {
+        let result = frame_to_gray(&*frame);
+        obs_source_release_frame(source, frame);
+        result
+    };
+    obs_source_release(source);
+    result
+}
 
-        // Store the preprocessed frame
-        let mut preprocessed_frame = self.preprocessed_frame.write().await;
-        *preprocessed_frame = Some(preprocessed);
+// The pixels are copied by the conversion, before the frame is released
+unsafe fn frame_to_gray(frame: &obs_source_frame) -> Result<Option<Mat>> {
+    let code = match frame.format {
+        video_format_VIDEO_FORMAT_RGBA => imgproc::COLOR_RGBA2GRAY,
+        video_format_VIDEO_FORMAT_BGRA | video_format_VIDEO_FORMAT_BGRX => imgproc::COLOR_BGRA2GRAY,
+        _ => return Ok(None),
+    };
+
+    let pixels = Mat::new_rows_cols_with_data_unsafe(
+        frame.height as i32,
+        frame.width as i32,
+        CV_8UC4,
+        frame.data[0] as *mut c_void,
+        frame.linesize[0] as usize,
+    )?;
+    let mut gray = Mat::default();
+    imgproc::cvt_color(&pixels, &mut gray, code, 0)?;
+    Ok(Some(preprocess_template(&gray)?))
+}
 
-        Ok(())
-    }
+/// Runs the detection of one overlay source: at most one frame at a time
+/// and no more often than `DETECTION_INTERVAL`. Results go to the source's
+/// renderer through the channel it was created with.
+pub struct FrameDetection {
+    results: mpsc::UnboundedSender<DetectionResult>,
+    // Fees the outputs of crafts and barters are valued after
+    fees: FleaFeeCalculator,
+    in_flight: Arc<AtomicBool>,
+    last_run: Mutex<Option<Instant>>,
+}
 
-    fn preprocess_frame(&self, frame: &mut Mat) -> Result<()> {
-        // Convert to grayscale
-        imgproc::cvt_color(frame, frame, imgproc::COLOR_BGR2GRAY, 0)?;
-
-        // Apply Gaussian blur to reduce noise
-        imgproc::gaussian_blur(
-            frame,
-            frame,
-            opencv::core::Size::new(5, 5),
-            0.0,
-            0.0,
-            opencv::core::BORDER_DEFAULT,
-        )?;
-
-        // Apply adaptive thresholding
-        imgproc::adaptive_threshold(
-            frame,
-            frame,
-            255.0,
-            imgproc::ADAPTIVE_THRESH_GAUSSIAN_C,
-            imgproc::THRESH_BINARY,
-            11,
-            2.0,
-        )?;
-
-        Ok(())
+impl FrameDetection {
+    pub fn new(results: mpsc::UnboundedSender<DetectionResult>, fees: FleaFeeCalculator) -> Self {
+        Self {
+            results,
+            fees,
+            in_flight: Arc::new(AtomicBool::new(false)),
+            last_run: Mutex::new(None),
+        }
     }
 
-    pub async fn get_last_frame(&self) -> Option<Mat> {
-        self.last_frame.read().await.clone()
-    }
+    /// Whether a new frame should be grabbed: the last detection finished
+    /// and the interval passed. Counts as the start of the next detection.
+    pub fn is_due(&self) -> bool {
+        if self.in_flight.load(Ordering::Acquire) {
+            return false;
+        }
 
-    pub async fn get_preprocessed_frame(&self) -> Option<Mat> {
-        self.preprocessed_frame.read().await.clone()
+        let mut last_run = self.last_run.lock().unwrap();
+        if last_run.is_some_and(|at| at.elapsed() < DETECTION_INTERVAL) {
+            return false;
+        }
+        *last_run = Some(Instant::now());
+        true
     }
 
-    pub fn get_dimensions(&self) -> (i32, i32) {
-        (self.width, self.height)
+    /// Matches the templates against the frame on the runtime, resolves the
+    /// matches with the provider and looks up their best uses in `recipes`.
+    pub fn detect<P: PriceProvider + 'static>(
+        &self,
+        runtime: &Handle,
+        frame: Mat,
+        matcher: Arc<TemplateMatcher>,
+        provider: Arc<P>,
+        recipes: Option<Arc<RwLock<RecipeBook>>>,
+    ) {
+        self.in_flight.store(true, Ordering::Release);
+        let in_flight = self.in_flight.clone();
+        let results = self.results.clone();
+        let fees = self.fees;
+
+        runtime.spawn(async move {
+            match matcher.match_templates(&frame).await {
+                Ok(matches) => {
+                    let result = match resolve_matches(provider.as_ref(), &matches).await {
+                        Ok(items) => {
+                            let best_uses = match &recipes {
+                                Some(recipes) => best_uses(provider.as_ref(), &*recipes.read().await, &items, &fees).await,
+                                None => Default::default(),
+                            };
+                            Ok(Detections { items, best_uses })
+                        },
+                        Err(e) => Err(e),
+                    };
+                    let _ = results.send(result);
+                },
+                Err(e) => warn!("Template matching failed: {:#}", e),
+            }
+            in_flight.store(false, Ordering::Release);
+        });
     }
 }
 
 pub struct TemplateMatcher {
-    templates: Arc<RwLock<Option<TemplateManager>>>,
+    templates: Arc<TemplateManager>,
     threshold: f64,
 }
 
 impl TemplateMatcher {
-    pub fn new(threshold: f64) -> Self {
+    pub fn new(templates: Arc<TemplateManager>, threshold: f64) -> Self {
         Self {
-            templates: Arc::new(RwLock::new(None)),
+            templates,
             threshold,
         }
     }
 
-    pub fn set_template_manager(&mut self, template_manager: Arc<TemplateManager>) {
-        let mut templates = self.templates.blocking_write();
-        *templates = Some(template_manager.clone());
-    }
-
-    pub fn set_threshold(&mut self, threshold: f64) {
-        self.threshold = threshold;
-    }
-
     pub async fn match_templates(&self, frame: &Mat) -> Result<Vec<(Rect, String)>> {
         let mut matches = Vec::new();
-        
-        // Get the template manager
-        let templates = self.templates.read().await;
-        if templates.is_none() {
-            return Ok(matches);
-        }
-        
-        let template_manager = templates.as_ref().unwrap();
-        
+
         // Get all template IDs
-        let template_ids = template_manager.get_all_template_ids().await;
-        
+        let template_ids = self.templates.get_all_template_ids().await;
+
         // Match each template
         for item_uid in template_ids {
             // Get the template
-            if let Some(template) = template_manager.get_template(&item_uid).await {
+            if let Some(template) = self.templates.get_template(&item_uid).await {
+                // A template larger than the frame can't be in it
+                if template.cols() > frame.cols() || template.rows() > frame.rows() {
+                    continue;
+                }
+
                 // Match the template
                 let mut result = Mat::default();
                 imgproc::match_template(
@@ -132,7 +182,7 @@ impl TemplateMatcher {
                     imgproc::TM_CCOEFF_NORMED,
                     &Mat::default(),
                 )?;
-                
+
                 // Find the best match
                 let mut max_val = 0.0;
                 let mut max_loc = Point::new(0, 0);
@@ -144,7 +194,7 @@ impl TemplateMatcher {
                     Some(&mut max_loc),
                     &Mat::default(),
                 )?;
-                
+
                 // If the match is good enough, add it to the matches
                 if max_val >= self.threshold {
                     let rect = Rect::new(
@@ -157,7 +207,7 @@ impl TemplateMatcher {
                 }
             }
         }
-        
+
         Ok(matches)
     }
-} 
\ No newline at end of file
+}
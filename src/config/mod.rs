@@ -3,6 +3,8 @@ use std::sync::RwLock;
 use lazy_static::lazy_static;
 use std::path::PathBuf;
 use thiserror::Error;
+use crate::api::{Currency, MarketMode, ProviderKind, DEFAULT_BASE_URL};
+use crate::market::{AnomalyBounds, HighlightTier, LootScore, ValueBasis};
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -16,7 +18,15 @@ pub enum ConfigError {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// Price backend, "tarkov_market" or "tarkov_dev"
+    #[serde(default)]
+    pub provider: ProviderKind,
+    /// Tarkov Market API key, not needed for tarkov.dev
+    #[serde(default)]
     pub api_key: String,
+    /// Base URL of the Tarkov Market API, overridable to point at a local mock server
+    #[serde(default = "default_api_base_url")]
+    pub api_base_url: String,
     pub min_value: u32,
     pub detection_threshold: f32,
     pub highlight_enabled: bool,
@@ -25,6 +35,156 @@ pub struct Config {
     pub tooltip_font_size: u32,
     pub tooltip_font_color: [f32; 4],
     pub data_dir: PathBuf,
+    /// Never contact the price API, serve the last cached snapshot instead
+    #[serde(default)]
+    pub offline_mode: bool,
+    /// Number of API requests that may be sent back to back
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    /// Sustained API request rate
+    #[serde(default = "default_rate_limit_per_second")]
+    pub rate_limit_per_second: f32,
+    /// Maximum API requests per day, 0 for no limit
+    #[serde(default)]
+    pub daily_request_quota: u32,
+    /// Seconds between background refreshes of the full price list
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u32,
+    /// Days of price history to keep
+    #[serde(default = "default_history_retention_days")]
+    pub history_retention_days: u32,
+    /// Seconds a single item lookup stays fresh in the cache
+    #[serde(default = "default_cache_item_ttl_secs")]
+    pub cache_item_ttl_secs: u64,
+    /// Seconds a search result stays fresh in the cache
+    #[serde(default = "default_cache_search_ttl_secs")]
+    pub cache_search_ttl_secs: u64,
+    /// Seconds the full item list stays fresh in the cache
+    #[serde(default = "default_cache_all_items_ttl_secs")]
+    pub cache_all_items_ttl_secs: u64,
+    /// Maximum number of cached items and of cached searches
+    #[serde(default = "default_cache_max_entries")]
+    pub cache_max_entries: usize,
+    /// Seconds expired entries are kept for offline use before being evicted
+    #[serde(default = "default_cache_stale_retention_secs")]
+    pub cache_stale_retention_secs: u64,
+    /// Language item names are shown in, e.g. "en", "ru", "de"
+    #[serde(default = "default_display_language")]
+    pub display_language: String,
+    /// Additional languages whose item names are searchable
+    #[serde(default)]
+    pub search_languages: Vec<String>,
+    /// Flea market prices are taken from, "pvp" or "pve"
+    #[serde(default)]
+    pub market_mode: MarketMode,
+    /// Hideout intel center level (0-3), level 3 lowers flea market fees
+    #[serde(default)]
+    pub intel_center_level: u8,
+    /// Show prices after the flea market fee instead of the listing price
+    #[serde(default)]
+    pub show_net_price: bool,
+    /// Tell in the tooltip when a trader pays more than the flea after fees
+    #[serde(default)]
+    pub show_sale_recommendation: bool,
+    /// Whether `min_value` and `highlight_tiers` apply to the total price or
+    /// the price per inventory slot
+    #[serde(default)]
+    pub value_basis: ValueBasis,
+    /// Highlight colors by value, `highlight_color` is used below the lowest tier
+    #[serde(default)]
+    pub highlight_tiers: Vec<HighlightTier>,
+    /// Loot score formula, e.g. "max(flea_net, trader_price) / slots". When
+    /// set it replaces `value_basis` for filtering, highlighting and ordering
+    #[serde(default)]
+    pub loot_score: Option<String>,
+    /// Highlight items still needed for quests or the hideout regardless of
+    /// `min_value`
+    #[serde(default = "default_highlight_needed_items")]
+    pub highlight_needed_items: bool,
+    #[serde(default = "default_needed_highlight_color")]
+    pub needed_highlight_color: [f32; 4],
+    /// Currency tooltip prices are shown in, "RUB", "USD" or "EUR"
+    #[serde(default)]
+    pub display_currency: Currency,
+    /// Add the rouble value to prices shown in dollars or euros
+    #[serde(default = "default_show_rouble_equivalent")]
+    pub show_rouble_equivalent: bool,
+    /// Local URL watchlist alerts are posted to as JSON, e.g.
+    /// "http://127.0.0.1:8080/tarkov-alerts"
+    #[serde(default)]
+    pub alert_webhook_url: Option<String>,
+    /// Seconds an alert toast stays on screen
+    #[serde(default = "default_alert_toast_secs")]
+    pub alert_toast_secs: u32,
+    /// Mark flea prices far from their 24h or 7 day average as suspicious
+    #[serde(default = "default_flag_price_anomalies")]
+    pub flag_price_anomalies: bool,
+    #[serde(default)]
+    pub anomaly_bounds: AnomalyBounds,
+}
+
+fn default_api_base_url() -> String {
+    DEFAULT_BASE_URL.to_string()
+}
+
+fn default_rate_limit_burst() -> u32 {
+    5
+}
+
+fn default_rate_limit_per_second() -> f32 {
+    5.0
+}
+
+fn default_refresh_interval() -> u32 {
+    300
+}
+
+fn default_history_retention_days() -> u32 {
+    30
+}
+
+fn default_cache_item_ttl_secs() -> u64 {
+    300
+}
+
+fn default_cache_search_ttl_secs() -> u64 {
+    300
+}
+
+fn default_cache_all_items_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_cache_max_entries() -> usize {
+    5000
+}
+
+fn default_cache_stale_retention_secs() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_display_language() -> String {
+    "en".to_string()
+}
+
+fn default_highlight_needed_items() -> bool {
+    true
+}
+
+fn default_needed_highlight_color() -> [f32; 4] {
+    [0.0, 0.8, 1.0, 0.5]
+}
+
+fn default_show_rouble_equivalent() -> bool {
+    true
+}
+
+fn default_alert_toast_secs() -> u32 {
+    8
+}
+
+fn default_flag_price_anomalies() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -33,7 +193,9 @@ impl Default for Config {
         data_dir.push("tarkov-price-overlay");
         
         Self {
+            provider: ProviderKind::default(),
             api_key: String::new(),
+            api_base_url: default_api_base_url(),
             min_value: 10000,
             detection_threshold: 0.8,
             highlight_enabled: true,
@@ -42,21 +204,86 @@ impl Default for Config {
             tooltip_font_size: 16,
             tooltip_font_color: [1.0, 1.0, 1.0, 1.0],
             data_dir,
+            offline_mode: false,
+            rate_limit_burst: default_rate_limit_burst(),
+            rate_limit_per_second: default_rate_limit_per_second(),
+            daily_request_quota: 0,
+            refresh_interval: default_refresh_interval(),
+            history_retention_days: default_history_retention_days(),
+            cache_item_ttl_secs: default_cache_item_ttl_secs(),
+            cache_search_ttl_secs: default_cache_search_ttl_secs(),
+            cache_all_items_ttl_secs: default_cache_all_items_ttl_secs(),
+            cache_max_entries: default_cache_max_entries(),
+            cache_stale_retention_secs: default_cache_stale_retention_secs(),
+            display_language: default_display_language(),
+            search_languages: Vec::new(),
+            market_mode: MarketMode::default(),
+            intel_center_level: 0,
+            show_net_price: false,
+            show_sale_recommendation: false,
+            value_basis: ValueBasis::default(),
+            highlight_tiers: Vec::new(),
+            loot_score: None,
+            highlight_needed_items: default_highlight_needed_items(),
+            needed_highlight_color: default_needed_highlight_color(),
+            display_currency: Currency::default(),
+            show_rouble_equivalent: default_show_rouble_equivalent(),
+            alert_webhook_url: None,
+            alert_toast_secs: default_alert_toast_secs(),
+            flag_price_anomalies: default_flag_price_anomalies(),
+            anomaly_bounds: AnomalyBounds::default(),
         }
     }
 }
 
 impl Config {
+    /// Whether the provider needs an API key that hasn't been entered yet.
+    /// Prices aren't refreshed until it is.
+    pub fn needs_api_key(&self) -> bool {
+        self.provider.requires_api_key() && self.api_key.is_empty()
+    }
+
     pub fn validate(&self) -> Result<(), ConfigError> {
-        if self.api_key.is_empty() {
-            return Err(ConfigError::ValidationError("API key cannot be empty".into()));
-        }
         if self.min_value < 0 {
             return Err(ConfigError::ValidationError("Minimum value threshold cannot be negative".into()));
         }
+        if !self.api_base_url.starts_with("http://") && !self.api_base_url.starts_with("https://") {
+            return Err(ConfigError::ValidationError("API base URL must start with http:// or https://".into()));
+        }
         if self.detection_threshold < 0.0 || self.detection_threshold > 1.0 {
             return Err(ConfigError::ValidationError("Detection threshold must be between 0 and 1".into()));
         }
+        if self.rate_limit_burst == 0 || self.rate_limit_per_second <= 0.0 {
+            return Err(ConfigError::ValidationError("Rate limit must allow at least one request".into()));
+        }
+        if self.refresh_interval == 0 {
+            return Err(ConfigError::ValidationError("Refresh interval must be positive".into()));
+        }
+        if self.cache_max_entries == 0 {
+            return Err(ConfigError::ValidationError("Cache must hold at least one entry".into()));
+        }
+        if self.intel_center_level > 3 {
+            return Err(ConfigError::ValidationError("Intel center level must be between 0 and 3".into()));
+        }
+        if let Some(loot_score) = &self.loot_score {
+            if let Err(e) = LootScore::parse(loot_score) {
+                return Err(ConfigError::ValidationError(format!("Invalid loot score formula: {}", e)));
+            }
+        }
+        if self.display_language.is_empty() {
+            return Err(ConfigError::ValidationError("Display language cannot be empty".into()));
+        }
+        if let Some(url) = &self.alert_webhook_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(ConfigError::ValidationError("Alert webhook URL must start with http:// or https://".into()));
+            }
+        }
+        if self.anomaly_bounds.spike_percent <= 0.0 || self.anomaly_bounds.crash_percent <= 0.0 {
+            return Err(ConfigError::ValidationError("Anomaly bounds must be positive".into()));
+        }
+        if self.anomaly_bounds.crash_percent >= 100.0 {
+            return Err(ConfigError::ValidationError("Anomaly crash bound must be below 100%".into()));
+        }
         if self.tooltip_font_size <= 0 {
             return Err(ConfigError::ValidationError("Tooltip font size must be positive".into()));
         }
@@ -83,18 +310,24 @@ impl Config {
         let path = PathBuf::from(path);
         let config_str = std::fs::read_to_string(&path)?;
         let mut config: Config = serde_json::from_str(&config_str)?;
-        config.data_dir = path;
+        config.data_dir = path.parent().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
         config.validate()?;
         Self::set(config)
     }
 
     pub fn save_to_file(&self) -> Result<(), ConfigError> {
-        let path = self.data_dir.as_path();
+        std::fs::create_dir_all(&self.data_dir)?;
+        let path = self.data_dir.join("config.json");
         let config_str = serde_json::to_string_pretty(self)?;
         std::fs::write(path, config_str)?;
         Ok(())
     }
 
+    /// Directory holding the persisted price cache snapshots
+    pub fn cache_dir(&self) -> PathBuf {
+        self.data_dir.join("cache")
+    }
+
     pub fn get_default_config_path() -> PathBuf {
         let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push("tarkov-price-overlay");
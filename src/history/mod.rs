@@ -0,0 +1,366 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+use crate::api::{Item, MarketMode};
+
+// Percent change below which an item is considered flat
+const FLAT_THRESHOLD_PERCENT: f64 = 1.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceRecord {
+    pub uid: String,
+    pub price: i32,
+    pub avg_24h_price: i32,
+    pub avg_7days_price: i32,
+    pub trader_price: i32,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+impl PriceRecord {
+    fn from_item(item: &Item, timestamp: u64) -> Self {
+        Self {
+            uid: item.uid.clone(),
+            price: item.price,
+            avg_24h_price: item.avg_24h_price,
+            avg_7days_price: item.avg_7days_price,
+            trader_price: item.trader_price,
+            timestamp,
+        }
+    }
+
+    fn same_prices(&self, other: &PriceRecord) -> bool {
+        self.price == other.price
+            && self.avg_24h_price == other.avg_24h_price
+            && self.avg_7days_price == other.avg_7days_price
+            && self.trader_price == other.trader_price
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceStats {
+    pub min: i32,
+    pub max: i32,
+    pub average: f64,
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Up,
+    Down,
+    Flat,
+}
+
+impl Trend {
+    pub fn arrow(&self) -> &'static str {
+        match self {
+            Trend::Up => "▲",
+            Trend::Down => "▼",
+            Trend::Flat => "▶",
+        }
+    }
+}
+
+/// Append-only price history kept in a JSON lines file per market mode under
+/// the data directory, with an in-memory index per item uid for queries.
+///
+/// A record is only written when one of an item's prices changed since its
+/// previous record, so the history is a step function of the refreshes.
+pub struct PriceHistory {
+    path: PathBuf,
+    records: RwLock<HashMap<String, Vec<PriceRecord>>>,
+    // Keeps appends and rewrites of the file in the order of the index
+    // changes. Taken before the index lock is released, so file I/O never
+    // holds up queries.
+    file_lock: Mutex<()>,
+}
+
+impl PriceHistory {
+    pub fn open<P: AsRef<Path>>(data_dir: P, market_mode: MarketMode) -> Result<Self> {
+        let dir = data_dir.as_ref().join("history");
+        fs::create_dir_all(&dir)
+            .context(format!("Failed to create history directory: {:?}", dir))?;
+
+        // PvP keeps the file name from before market modes existed
+        let path = match market_mode {
+            MarketMode::Pvp => dir.join("prices.jsonl"),
+            MarketMode::Pve => dir.join(format!("prices-{}.jsonl", market_mode.as_str())),
+        };
+        let mut records: HashMap<String, Vec<PriceRecord>> = HashMap::new();
+
+        if path.exists() {
+            let file = fs::File::open(&path)
+                .context(format!("Failed to open price history: {:?}", path))?;
+
+            for (line_number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                // Skip corrupt lines (e.g. a partial write on crash) instead of losing the history
+                match serde_json::from_str::<PriceRecord>(&line) {
+                    Ok(record) => records.entry(record.uid.clone()).or_default().push(record),
+                    Err(e) => warn!("Skipping invalid price history line {}: {}", line_number + 1, e),
+                }
+            }
+        }
+
+        for item_records in records.values_mut() {
+            item_records.sort_by_key(|record| record.timestamp);
+        }
+
+        info!("Loaded price history for {} items", records.len());
+        Ok(Self {
+            path,
+            records: RwLock::new(records),
+            file_lock: Mutex::new(()),
+        })
+    }
+
+    /// Records the prices of a refresh. Returns the number of new records.
+    pub fn record(&self, items: &[Item]) -> Result<usize> {
+        let timestamp = unix_now();
+        let mut records = self.records.write().unwrap();
+        let mut lines = String::new();
+        let mut recorded = 0;
+
+        for item in items {
+            let record = PriceRecord::from_item(item, timestamp);
+            let item_records = records.entry(item.uid.clone()).or_default();
+            if item_records.last().is_some_and(|last| last.same_prices(&record)) {
+                continue;
+            }
+
+            lines.push_str(&serde_json::to_string(&record)?);
+            lines.push('\n');
+            item_records.push(record);
+            recorded += 1;
+        }
+
+        if recorded == 0 {
+            return Ok(0);
+        }
+
+        let _writing = self.file_lock.lock().unwrap();
+        drop(records);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open price history: {:?}", self.path))?;
+        file.write_all(lines.as_bytes())?;
+
+        Ok(recorded)
+    }
+
+    /// Records of an item within the window, oldest first, including the
+    /// record that was still in effect at the start of the window.
+    pub fn records(&self, uid: &str, window: Duration) -> Vec<PriceRecord> {
+        self.records_since(uid, unix_now().saturating_sub(window.as_secs()))
+    }
+
+    fn records_since(&self, uid: &str, since: u64) -> Vec<PriceRecord> {
+        let records = self.records.read().unwrap();
+        let Some(item_records) = records.get(uid) else {
+            return Vec::new();
+        };
+
+        let first_in_window = item_records.iter()
+            .position(|record| record.timestamp >= since)
+            .unwrap_or(item_records.len());
+        item_records[first_in_window.saturating_sub(1)..].to_vec()
+    }
+
+    /// Min, max and average flea price over the window. The price in effect
+    /// at the start of the window counts towards min and max, but only the
+    /// records within the window are averaged, unless there are none.
+    pub fn stats(&self, uid: &str, window: Duration) -> Option<PriceStats> {
+        let since = unix_now().saturating_sub(window.as_secs());
+        let records = self.records_since(uid, since);
+        if records.is_empty() {
+            return None;
+        }
+
+        let in_window: Vec<i32> = records.iter()
+            .filter(|record| record.timestamp >= since)
+            .map(|record| record.price)
+            .collect();
+        let samples = if in_window.is_empty() { vec![records[0].price] } else { in_window };
+
+        let prices = records.iter().map(|record| record.price);
+        Some(PriceStats {
+            min: prices.clone().min()?,
+            max: prices.max()?,
+            average: samples.iter().map(|price| *price as f64).sum::<f64>() / samples.len() as f64,
+            samples: samples.len(),
+        })
+    }
+
+    /// Percent change of the flea price between the price in effect at the
+    /// start of the window and the latest one.
+    pub fn percent_change(&self, uid: &str, window: Duration) -> Option<f64> {
+        let records = self.records(uid, window);
+        let start = records.first()?;
+        let latest = records.last()?;
+
+        if start.price == 0 {
+            return None;
+        }
+        Some((latest.price - start.price) as f64 / start.price as f64 * 100.0)
+    }
+
+    pub fn trend(&self, uid: &str, window: Duration) -> Option<Trend> {
+        let change = self.percent_change(uid, window)?;
+        Some(if change >= FLAT_THRESHOLD_PERCENT {
+            Trend::Up
+        } else if change <= -FLAT_THRESHOLD_PERCENT {
+            Trend::Down
+        } else {
+            Trend::Flat
+        })
+    }
+
+    /// Drops records older than `max_age` and rewrites the history file.
+    /// The most recent record of every item is always kept.
+    pub fn prune(&self, max_age: Duration) -> Result<usize> {
+        let since = unix_now().saturating_sub(max_age.as_secs());
+        let mut records = self.records.write().unwrap();
+        let mut removed = 0;
+
+        for item_records in records.values_mut() {
+            let keep_from = item_records.iter()
+                .position(|record| record.timestamp >= since)
+                .unwrap_or(item_records.len())
+                .min(item_records.len().saturating_sub(1));
+            removed += keep_from;
+            item_records.drain(..keep_from);
+        }
+
+        let mut lines = String::new();
+        for record in records.values().flatten() {
+            lines.push_str(&serde_json::to_string(record)?);
+            lines.push('\n');
+        }
+
+        let _writing = self.file_lock.lock().unwrap();
+        drop(records);
+
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&tmp_path, lines)?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        info!("Pruned {} price history records", removed);
+        Ok(removed)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HOUR: u64 = 60 * 60;
+
+    fn history(name: &str) -> PriceHistory {
+        let dir = std::env::temp_dir()
+            .join(format!("tarkov-price-overlay-{}", std::process::id()))
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        PriceHistory::open(&dir, MarketMode::Pvp).unwrap()
+    }
+
+    fn record(uid: &str, price: i32, age_secs: u64) -> PriceRecord {
+        PriceRecord {
+            uid: uid.to_string(),
+            price,
+            avg_24h_price: price,
+            avg_7days_price: price,
+            trader_price: 0,
+            timestamp: unix_now() - age_secs,
+        }
+    }
+
+    // Replaces the records of "a" with (price, age in seconds) pairs, oldest first
+    fn with_records(history: &PriceHistory, prices: &[(i32, u64)]) {
+        let records = prices.iter().map(|&(price, age)| record("a", price, age)).collect();
+        history.records.write().unwrap().insert("a".to_string(), records);
+    }
+
+    #[test]
+    fn stats_include_the_price_in_effect_at_the_window_start() {
+        let history = history("stats");
+        with_records(&history, &[(100, 2 * HOUR), (200, HOUR / 2), (150, HOUR / 6)]);
+
+        let stats = history.stats("a", Duration::from_secs(HOUR)).unwrap();
+        assert_eq!((stats.min, stats.max), (100, 200));
+        assert_eq!(stats.samples, 2);
+        assert_eq!(stats.average, 175.0);
+        assert!(history.stats("b", Duration::from_secs(HOUR)).is_none());
+    }
+
+    #[test]
+    fn stats_without_records_in_the_window_use_the_last_price() {
+        let history = history("stats-old");
+        with_records(&history, &[(100, 2 * HOUR)]);
+
+        let stats = history.stats("a", Duration::from_secs(HOUR)).unwrap();
+        assert_eq!(stats.samples, 1);
+        assert_eq!(stats.average, 100.0);
+    }
+
+    #[test]
+    fn percent_change_compares_the_window_start_with_the_latest_price() {
+        let history = history("percent-change");
+        with_records(&history, &[(100, 2 * HOUR), (200, HOUR / 2), (150, HOUR / 6)]);
+
+        assert_eq!(history.percent_change("a", Duration::from_secs(HOUR)), Some(50.0));
+        assert_eq!(history.trend("a", Duration::from_secs(HOUR)), Some(Trend::Up));
+
+        with_records(&history, &[(0, 2 * HOUR), (150, HOUR / 6)]);
+        assert_eq!(history.percent_change("a", Duration::from_secs(HOUR)), None);
+    }
+
+    #[test]
+    fn prune_keeps_the_latest_record_of_every_item() {
+        let history = history("prune");
+        with_records(&history, &[(100, 3 * HOUR), (200, 2 * HOUR), (150, HOUR / 6)]);
+        history.records.write().unwrap().insert("b".to_string(), vec![record("b", 10, 3 * HOUR)]);
+
+        assert_eq!(history.prune(Duration::from_secs(HOUR)).unwrap(), 2);
+        assert_eq!(history.records("a", Duration::from_secs(4 * HOUR)).len(), 1);
+        assert_eq!(history.records("b", Duration::from_secs(4 * HOUR)).len(), 1);
+
+        // The file was rewritten with the remaining records
+        let reopened = PriceHistory::open(history.path.parent().unwrap().parent().unwrap(), MarketMode::Pvp).unwrap();
+        assert_eq!(reopened.records("a", Duration::from_secs(4 * HOUR))[0].price, 150);
+    }
+
+    #[test]
+    fn record_skips_unchanged_prices() {
+        let history = history("record");
+        let items: Vec<Item> = serde_json::from_value(json!([
+            { "uid": "a", "price": 100 },
+            { "uid": "b", "price": 200 },
+        ])).unwrap();
+
+        assert_eq!(history.record(&items).unwrap(), 2);
+        assert_eq!(history.record(&items).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&history.path).unwrap().lines().count(), 2);
+    }
+}
@@ -1,8 +1,33 @@
 use obs_sys::*;
 use obs_text::*;
-use std::ffi::CString;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_void};
-use std::sync::Mutex;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod alerts;
+mod capture;
+pub mod api;
+pub mod config;
+mod history;
+mod market;
+mod needs;
+mod overlay;
+mod scheduler;
+mod template;
+
+use alerts::{AlertCondition, AlertEngine, Watchlist, WebhookSink};
+use capture::{FrameDetection, TemplateMatcher};
+use api::{ApiError, PriceBackend, PriceProvider};
+use config::Config;
+use history::PriceHistory;
+use market::{FleaFeeCalculator, RecipeBook};
+use needs::NeededItems;
+use overlay::OverlayRenderer;
+use scheduler::{PriceEvent, PriceRefreshScheduler, MIN_REFRESH_INTERVAL};
+use template::TemplateManager;
 
 // OBS constants
 const OBS_SOURCE_VIDEO: u32 = 1;
@@ -13,6 +38,14 @@ const OBS_SOURCE_ASYNC: u32 = 4;
 const OBS_TEXT_DEFAULT: u32 = 0;
 const OBS_TEXT_PASSWORD: u32 = 1;
 const OBS_TEXT_MULTILINE: u32 = 2;
+const OBS_TEXT_INFO: u32 = 3;
+
+// OBS path type constants
+const OBS_PATH_FILE: u32 = 0;
+
+// OBS combo box constants
+const OBS_COMBO_TYPE_LIST: u32 = 2;
+const OBS_COMBO_FORMAT_STRING: u32 = 3;
 
 // Basic OBS structs for FFI
 #[repr(C)]
@@ -25,6 +58,7 @@ struct obs_source_info {
     destroy: Option<extern "C" fn(*mut c_void)>,
     get_properties: Option<extern "C" fn(*mut c_void) -> *mut obs_properties_t>,
     update: Option<extern "C" fn(*mut c_void, *mut c_void)>,
+    video_tick: Option<extern "C" fn(*mut c_void, f32)>,
     video_render: Option<extern "C" fn(*mut c_void, *mut c_void)>,
     _padding: [u64; 20],
 }
@@ -36,9 +70,105 @@ pub extern "C" fn obs_register_source(_info: *mut obs_source_info) {
     println!("Mock: obs_register_source called");
 }
 
+// State shared by all sources, alive between obs_module_load and obs_module_unload
+struct PluginState {
+    runtime: tokio::runtime::Runtime,
+    api: Arc<PriceBackend>,
+    evict_task: tokio::task::JoinHandle<()>,
+    // Not started until the provider has the API key it needs
+    scheduler: Option<PriceRefreshScheduler>,
+    history: Option<Arc<PriceHistory>>,
+    alerts: Option<Arc<AlertEngine>>,
+    recipes: Option<Arc<tokio::sync::RwLock<RecipeBook>>>,
+    needed_items: Option<Arc<NeededItems>>,
+    // Item icons the detection matches frames against
+    templates: Arc<TemplateManager>,
+    matcher: Arc<TemplateMatcher>,
+    // Refresh interval in seconds each live source asks for (0 if none),
+    // keyed by the address of its TarkovPriceOverlayData
+    sources: HashMap<usize, u32>,
+}
+
+impl PluginState {
+    // Starts refreshing prices for the history, the alerts and every source,
+    // replacing a running scheduler. Does nothing while the API key is missing.
+    fn start_scheduler(&mut self, config: &Config) {
+        drop(self.scheduler.take());
+        if config.needs_api_key() {
+            log::info!("No {} API key set, prices are refreshed once one is entered", self.api.name());
+            return;
+        }
+
+        let scheduler = {
+            let _guard = self.runtime.enter();
+            PriceRefreshScheduler::start(self.api.clone(), self.refresh_interval(config))
+        };
+        if let Some(history) = &self.history {
+            self.runtime.spawn(record_price_history(history.clone(), scheduler.subscribe()));
+        }
+        if let Some(alerts) = &self.alerts {
+            self.runtime.spawn(evaluate_alerts(alerts.clone(), scheduler.subscribe()));
+        }
+        if let Some(recipes) = &self.recipes {
+            self.runtime.spawn(refresh_recipes(recipes.clone(), self.api.clone(), config.data_dir.clone()));
+        }
+        for &source in self.sources.keys() {
+            // Sources are removed in destroy before they are freed
+            let data = unsafe { &*(source as *const TarkovPriceOverlayData) };
+            let mut renderer = data.renderer.lock().unwrap();
+            renderer.set_error(None);
+            renderer.subscribe_prices(scheduler.subscribe());
+        }
+        self.scheduler = Some(scheduler);
+    }
+
+    // Rebuilds the price backend with a newly entered API key and restarts
+    // the refreshes with it
+    fn set_api_key(&mut self, api_key: String) {
+        let mut config = Config::get();
+        if !config.provider.requires_api_key() || config.api_key == api_key {
+            return;
+        }
+
+        config.api_key = api_key;
+        if let Err(e) = Config::set(config.clone()) {
+            log::warn!("Failed to set the API key: {}", e);
+            return;
+        }
+
+        self.evict_task.abort();
+        self.api = Arc::new(PriceBackend::from_config(&config));
+        self.evict_task = self.runtime.spawn(evict_cache_entries(self.api.clone()));
+        self.start_scheduler(&config);
+    }
+
+    // The shortest interval any source asks for, so one source can't slow
+    // down another; the config's interval when none asks. Never below
+    // MIN_REFRESH_INTERVAL.
+    fn refresh_interval(&self, config: &Config) -> Duration {
+        let secs = self.sources.values()
+            .copied()
+            .filter(|&secs| secs > 0)
+            .min()
+            .unwrap_or(config.refresh_interval);
+        Duration::from_secs(secs as u64).max(MIN_REFRESH_INTERVAL)
+    }
+
+    fn update_refresh_interval(&self) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.set_interval(self.refresh_interval(&Config::get()));
+        }
+    }
+}
+
+static PLUGIN_STATE: Mutex<Option<PluginState>> = Mutex::new(None);
+
 // Plugin data structure
 struct TarkovPriceOverlayData {
+    source: *mut c_void,
     settings: Mutex<Settings>,
+    renderer: Mutex<OverlayRenderer>,
+    detection: FrameDetection,
 }
 
 #[derive(Default)]
@@ -46,8 +176,35 @@ struct Settings {
     api_key: String,
     refresh_interval: u32,
     font_size: u32,
+    // Name of the OBS source whose frames items are detected in
+    capture_source: String,
+    // Item name the template uids are suggested for, the chosen uid and the
+    // image the "Add Template" button saves as its template
+    template_item: String,
+    template_uid: String,
+    template_image: String,
     position_x: u32,
     position_y: u32,
+    // Item and conditions the "Watch Item" button adds to the watchlist
+    watch_query: String,
+    watch_conditions: Vec<AlertCondition>,
+    // Quest or hideout level the progress buttons act on
+    progress_requirement: String,
+    // Item name and count the "Set Collected" button saves
+    collected_item: String,
+    collected_count: u32,
+}
+
+// String setting, empty if it isn't set
+unsafe fn settings_string(settings: *mut c_void, name: &str) -> String {
+    let value = obs_data_get_string(
+        settings as *mut obs_data_t,
+        CString::new(name).unwrap().as_ptr(),
+    );
+    if value.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(value).to_string_lossy().trim().to_string()
 }
 
 // Plugin API functions
@@ -59,16 +216,434 @@ extern "C" fn tarkov_price_overlay_get_name(_data: *mut c_void) -> *const c_char
 
 extern "C" fn tarkov_price_overlay_create(
     settings: *mut c_void,
-    _source: *mut c_void,
+    source: *mut c_void,
 ) -> *mut c_void {
+    let config = Config::get();
+    let (detection_results, detections) = tokio::sync::mpsc::unbounded_channel();
+    let mut renderer = OverlayRenderer::from_config(&config);
+    renderer.receive_detections(detections);
     let data = Box::new(TarkovPriceOverlayData {
+        source,
         settings: Mutex::new(Settings::default()),
+        renderer: Mutex::new(renderer),
+        detection: FrameDetection::new(detection_results, FleaFeeCalculator::from_config(&config)),
     });
+
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_mut() {
+        state.sources.insert(Box::as_ref(&data) as *const _ as usize, 0);
+
+        let mut renderer = data.renderer.lock().unwrap();
+        match &state.scheduler {
+            Some(scheduler) => renderer.subscribe_prices(scheduler.subscribe()),
+            None => renderer.set_error(Some(ApiError::MissingApiKey)),
+        }
+        if let Some(history) = &state.history {
+            renderer.set_price_history(history.clone());
+        }
+        if let Some(alerts) = &state.alerts {
+            renderer.subscribe_alerts(alerts.subscribe());
+        }
+        if let Some(needed_items) = &state.needed_items {
+            if config.highlight_needed_items {
+                renderer.set_needed_items(needed_items.clone(), config.needed_highlight_color);
+            }
+        }
+    }
+
+    tarkov_price_overlay_update(Box::as_ref(&data) as *const _ as *mut c_void, settings);
     Box::into_raw(data) as *mut c_void
 }
 
+extern "C" fn tarkov_price_overlay_update(data: *mut c_void, settings: *mut c_void) {
+    if data.is_null() || settings.is_null() {
+        return;
+    }
+
+    let source = data as usize;
+    let data = unsafe { &*(data as *const TarkovPriceOverlayData) };
+    let font_size = unsafe {
+        obs_data_get_int(
+            settings as *mut obs_data_t,
+            CString::new("font_size").unwrap().as_ptr(),
+        )
+    };
+    if font_size > 0 {
+        data.settings.lock().unwrap().font_size = font_size as u32;
+        let config = Config::get();
+        data.renderer.lock().unwrap().update_settings(config.highlight_color, font_size as f32, config.tooltip_font_color);
+    }
+
+    let api_key = unsafe { settings_string(settings, "api_key") };
+    data.settings.lock().unwrap().api_key = api_key.clone();
+
+    let (watch_above, watch_below, watch_deviation) = unsafe {
+        let get_int = |name: &str| obs_data_get_int(settings as *mut obs_data_t, CString::new(name).unwrap().as_ptr());
+        (get_int("watch_above"), get_int("watch_below"), get_int("watch_deviation"))
+    };
+    let mut watch_conditions = Vec::new();
+    if watch_above > 0 {
+        watch_conditions.push(AlertCondition::Above { price: watch_above as i32 });
+    }
+    if watch_below > 0 {
+        watch_conditions.push(AlertCondition::Below { price: watch_below as i32 });
+    }
+    if watch_deviation > 0 {
+        watch_conditions.push(AlertCondition::DeviatesFromAvg24h { percent: watch_deviation as f64 });
+    }
+    let collected_count = unsafe {
+        obs_data_get_int(
+            settings as *mut obs_data_t,
+            CString::new("collected_count").unwrap().as_ptr(),
+        )
+    };
+    {
+        let mut data_settings = data.settings.lock().unwrap();
+        data_settings.capture_source = unsafe { settings_string(settings, "capture_source") };
+        data_settings.template_item = unsafe { settings_string(settings, "template_item") };
+        data_settings.template_uid = unsafe { settings_string(settings, "template_uid") };
+        data_settings.template_image = unsafe { settings_string(settings, "template_image") };
+        data_settings.watch_query = unsafe { settings_string(settings, "watch_query") };
+        data_settings.watch_conditions = watch_conditions;
+        data_settings.progress_requirement = unsafe { settings_string(settings, "progress_requirement") };
+        data_settings.collected_item = unsafe { settings_string(settings, "collected_item") };
+        data_settings.collected_count = collected_count.max(0) as u32;
+    }
+
+    let refresh_interval = unsafe {
+        obs_data_get_int(
+            settings as *mut obs_data_t,
+            CString::new("update_interval").unwrap().as_ptr(),
+        )
+    };
+    let refresh_interval = refresh_interval.max(0) as u32;
+    data.settings.lock().unwrap().refresh_interval = refresh_interval;
+
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_mut() {
+        if !api_key.is_empty() {
+            state.set_api_key(api_key);
+        }
+        if let Some(interval) = state.sources.get_mut(&source) {
+            *interval = refresh_interval;
+        }
+        state.update_refresh_interval();
+    }
+}
+
+// Starts a detection on the capture source's current frame when the last
+// one finished and the detection interval passed
+extern "C" fn tarkov_price_overlay_video_tick(data: *mut c_void, _seconds: f32) {
+    if data.is_null() {
+        return;
+    }
+
+    let data = unsafe { &*(data as *const TarkovPriceOverlayData) };
+    let capture_source = data.settings.lock().unwrap().capture_source.clone();
+    if capture_source.is_empty() || !data.detection.is_due() {
+        return;
+    }
+
+    // Only hold the state lock long enough to clone what the detection needs
+    let Some((runtime, matcher, api, recipes)) = PLUGIN_STATE.lock().unwrap().as_ref()
+        .map(|state| (state.runtime.handle().clone(), state.matcher.clone(), state.api.clone(), state.recipes.clone()))
+    else {
+        return;
+    };
+
+    match unsafe { capture::grab_frame(&capture_source) } {
+        Ok(Some(frame)) => data.detection.detect(&runtime, frame, matcher, api, recipes),
+        Ok(None) => {},
+        Err(e) => log::debug!("No frame from {:?}: {:#}", capture_source, e),
+    }
+}
+
+extern "C" fn tarkov_price_overlay_video_render(data: *mut c_void, _effect: *mut c_void) {
+    if data.is_null() {
+        return;
+    }
+
+    let data = unsafe { &*(data as *const TarkovPriceOverlayData) };
+    data.renderer.lock().unwrap().render(data.source as *mut obs_source_t);
+}
+
+// Pins the item searched for in the watch settings
+unsafe extern "C" fn tarkov_price_overlay_watch_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let (query, conditions) = {
+        let settings = data.settings.lock().unwrap();
+        (settings.watch_query.clone(), settings.watch_conditions.clone())
+    };
+    if query.is_empty() || conditions.is_empty() {
+        log::warn!("Enter an item and at least one alert condition to watch");
+        return false;
+    }
+
+    let state = PLUGIN_STATE.lock().unwrap();
+    let Some(state) = state.as_ref() else {
+        return false;
+    };
+    let Some(alerts) = &state.alerts else {
+        log::warn!("Price alerts are disabled, can't watch {:?}", query);
+        return false;
+    };
+
+    let watchlist = alerts.watchlist().clone();
+    let api = state.api.clone();
+    state.runtime.spawn(async move {
+        match watchlist.watch_search(api.as_ref(), &query, conditions).await {
+            Ok(entry) => log::info!("Watching {} for price alerts", entry.name),
+            Err(e) => log::warn!("Failed to watch {:?}: {}", query, e),
+        }
+    });
+    false
+}
+
+// Unpins the item entered in the watch settings, by its watched name or
+// else the best match of the offline search index
+unsafe extern "C" fn tarkov_price_overlay_unwatch_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let query = data.settings.lock().unwrap().watch_query.clone();
+    if query.is_empty() {
+        return false;
+    }
+
+    let state = PLUGIN_STATE.lock().unwrap();
+    let Some(state) = state.as_ref() else {
+        return false;
+    };
+    let Some(alerts) = &state.alerts else {
+        return false;
+    };
+
+    let watchlist = alerts.watchlist().clone();
+    let api = state.api.clone();
+    state.runtime.spawn(async move {
+        let watched = watchlist.entries()
+            .into_iter()
+            .find(|entry| entry.uid == query || entry.name.eq_ignore_ascii_case(&query));
+        let uid = match watched {
+            Some(entry) => entry.uid,
+            None => match api.search_offline(&query, 1).await.into_iter().next() {
+                Some(hit) => hit.item.uid,
+                None => query.clone(),
+            },
+        };
+        match watchlist.unwatch(&uid) {
+            Ok(true) => log::info!("Stopped watching {:?}", query),
+            Ok(false) => log::warn!("{:?} isn't watched", query),
+            Err(e) => log::warn!("Failed to unwatch {:?}: {}", query, e),
+        }
+    });
+    false
+}
+
+// Downloads the icons of all items that have no template yet
+unsafe extern "C" fn tarkov_price_overlay_download_templates_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    _data: *mut c_void,
+) -> bool {
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_ref() {
+        let templates = state.templates.clone();
+        let api = state.api.clone();
+        state.runtime.spawn(async move {
+            if let Err(e) = templates.download_templates(api.as_ref()).await {
+                log::warn!("Failed to download item templates: {:#}", e);
+            }
+        });
+    }
+    false
+}
+
+// Refreshes the properties to suggest the items the entered name matches
+unsafe extern "C" fn tarkov_price_overlay_find_template_item_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    _data: *mut c_void,
+) -> bool {
+    true
+}
+
+// Saves the chosen image as the template of the chosen item
+unsafe extern "C" fn tarkov_price_overlay_add_template_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let (uid, image) = {
+        let settings = data.settings.lock().unwrap();
+        (settings.template_uid.clone(), settings.template_image.clone())
+    };
+    if uid.is_empty() || image.is_empty() {
+        log::warn!("Choose an item and an image to add a template");
+        return false;
+    }
+
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_ref() {
+        let templates = state.templates.clone();
+        state.runtime.spawn(async move {
+            if let Err(e) = templates.import_template(&uid, std::path::Path::new(&image)).await {
+                log::warn!("Failed to add the template of {}: {:#}", uid, e);
+            }
+        });
+    }
+    false
+}
+
+// Deletes the template of the chosen item
+unsafe extern "C" fn tarkov_price_overlay_remove_template_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let uid = data.settings.lock().unwrap().template_uid.clone();
+    if uid.is_empty() {
+        return false;
+    }
+
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_ref() {
+        let templates = state.templates.clone();
+        state.runtime.spawn(async move {
+            if let Err(e) = templates.remove_template(&uid).await {
+                log::warn!("Failed to remove the template of {}: {:#}", uid, e);
+            }
+        });
+    }
+    false
+}
+
+// Downloads the crafts and barters again
+unsafe extern "C" fn tarkov_price_overlay_refresh_recipes_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    _data: *mut c_void,
+) -> bool {
+    if let Some(state) = PLUGIN_STATE.lock().unwrap().as_ref() {
+        if let Some(recipes) = &state.recipes {
+            state.runtime.spawn(refresh_recipes(recipes.clone(), state.api.clone(), Config::get().data_dir));
+        }
+    }
+    false
+}
+
+// Marks the selected quest or hideout level as done, or undoes that
+unsafe fn set_requirement_completed(data: *mut c_void, completed: bool) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let requirement_id = data.settings.lock().unwrap().progress_requirement.clone();
+    if requirement_id.is_empty() {
+        return false;
+    }
+    let needed_items = PLUGIN_STATE.lock().unwrap().as_ref().and_then(|state| state.needed_items.clone());
+    let Some(needed_items) = needed_items else {
+        return false;
+    };
+
+    let result = if completed {
+        needed_items.complete(&requirement_id)
+    } else {
+        needed_items.reopen(&requirement_id)
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to save progress: {:#}", e);
+    }
+    // Refresh the properties to update the requirement list
+    true
+}
+
+unsafe extern "C" fn tarkov_price_overlay_complete_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    set_requirement_completed(data, true)
+}
+
+unsafe extern "C" fn tarkov_price_overlay_reopen_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    set_requirement_completed(data, false)
+}
+
+// Saves how many of the entered item were set aside. The name is looked up
+// in the offline search index, which also accepts a uid.
+unsafe extern "C" fn tarkov_price_overlay_set_collected_clicked(
+    _props: *mut obs_properties_t,
+    _property: *mut obs_property_t,
+    data: *mut c_void,
+) -> bool {
+    if data.is_null() {
+        return false;
+    }
+
+    let data = &*(data as *const TarkovPriceOverlayData);
+    let (query, count) = {
+        let settings = data.settings.lock().unwrap();
+        (settings.collected_item.clone(), settings.collected_count)
+    };
+    if query.is_empty() {
+        return false;
+    }
+
+    let state = PLUGIN_STATE.lock().unwrap();
+    let Some(state) = state.as_ref() else {
+        return false;
+    };
+    let Some(needed_items) = state.needed_items.clone() else {
+        return false;
+    };
+
+    let api = state.api.clone();
+    state.runtime.spawn(async move {
+        let uid = match api.search_offline(&query, 1).await.into_iter().next() {
+            Some(hit) => hit.item.uid,
+            None => query.clone(),
+        };
+        match needed_items.set_collected(&uid, count) {
+            Ok(()) => log::info!("Set {} of {:?} as collected", count, query),
+            Err(e) => log::warn!("Failed to save progress: {:#}", e),
+        }
+    });
+    false
+}
+
 extern "C" fn tarkov_price_overlay_destroy(data: *mut c_void) {
     if !data.is_null() {
+        if let Some(state) = PLUGIN_STATE.lock().unwrap().as_mut() {
+            state.sources.remove(&(data as usize));
+            state.update_refresh_interval();
+        }
+
         unsafe {
             drop(Box::from_raw(data as *mut TarkovPriceOverlayData));
         }
@@ -76,7 +651,7 @@ extern "C" fn tarkov_price_overlay_destroy(data: *mut c_void) {
 }
 
 #[no_mangle]
-pub extern "C" fn get_properties(_data: *mut c_void) -> *mut obs_properties_t {
+pub extern "C" fn get_properties(data: *mut c_void) -> *mut obs_properties_t {
     unsafe {
         let props = obs_properties_create();
 
@@ -91,7 +666,7 @@ pub extern "C" fn get_properties(_data: *mut c_void) -> *mut obs_properties_t {
             props,
             CString::new("update_interval").unwrap().as_ptr(),
             CString::new("Update Interval (seconds)").unwrap().as_ptr(),
-            1,
+            MIN_REFRESH_INTERVAL.as_secs() as i32,
             3600,
             1,
         );
@@ -105,6 +680,302 @@ pub extern "C" fn get_properties(_data: *mut c_void) -> *mut obs_properties_t {
             1,
         );
 
+        obs_properties_add_text(
+            props,
+            CString::new("capture_source").unwrap().as_ptr(),
+            CString::new("Detect Items In (source name)").unwrap().as_ptr(),
+            OBS_TEXT_DEFAULT,
+        );
+
+        obs_properties_add_text(
+            props,
+            CString::new("watch_query").unwrap().as_ptr(),
+            CString::new("Watch Item").unwrap().as_ptr(),
+            OBS_TEXT_DEFAULT,
+        );
+
+        obs_properties_add_int(
+            props,
+            CString::new("watch_above").unwrap().as_ptr(),
+            CString::new("Alert Above (roubles, 0 for none)").unwrap().as_ptr(),
+            0,
+            100_000_000,
+            1000,
+        );
+
+        obs_properties_add_int(
+            props,
+            CString::new("watch_below").unwrap().as_ptr(),
+            CString::new("Alert Below (roubles, 0 for none)").unwrap().as_ptr(),
+            0,
+            100_000_000,
+            1000,
+        );
+
+        obs_properties_add_int(
+            props,
+            CString::new("watch_deviation").unwrap().as_ptr(),
+            CString::new("Alert at % From 24h Average (0 for none)").unwrap().as_ptr(),
+            0,
+            100,
+            1,
+        );
+
+        obs_properties_add_button(
+            props,
+            CString::new("watch_item").unwrap().as_ptr(),
+            CString::new("Watch Item").unwrap().as_ptr(),
+            Some(tarkov_price_overlay_watch_clicked),
+        );
+
+        obs_properties_add_button(
+            props,
+            CString::new("unwatch_item").unwrap().as_ptr(),
+            CString::new("Unwatch Item").unwrap().as_ptr(),
+            Some(tarkov_price_overlay_unwatch_clicked),
+        );
+
+        // Only hold the state lock long enough to clone what's shown
+        let (api, watchlist, recipes, needed_items, templates) = match PLUGIN_STATE.lock().unwrap().as_ref() {
+            Some(state) => (
+                Some(state.api.clone()),
+                state.alerts.as_ref().map(|alerts| alerts.watchlist().clone()),
+                state.recipes.clone(),
+                state.needed_items.clone(),
+                Some((state.templates.clone(), state.runtime.handle().clone())),
+            ),
+            None => (None, None, None, None, None),
+        };
+
+        if let Some((templates, runtime)) = templates {
+            // Skip the count while the templates are loaded
+            let templates_text = match templates.get_template_count() {
+                Some(count) => format!("Item templates: {}", count),
+                None => "Item templates: loading".to_string(),
+            };
+
+            obs_properties_add_text(
+                props,
+                CString::new("templates").unwrap().as_ptr(),
+                CString::new(templates_text).unwrap().as_ptr(),
+                OBS_TEXT_INFO,
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("download_templates").unwrap().as_ptr(),
+                CString::new("Download Item Templates").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_download_templates_clicked),
+            );
+
+            obs_properties_add_text(
+                props,
+                CString::new("template_item").unwrap().as_ptr(),
+                CString::new("Template Item").unwrap().as_ptr(),
+                OBS_TEXT_DEFAULT,
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("find_template_item").unwrap().as_ptr(),
+                CString::new("Find Item").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_find_template_item_clicked),
+            );
+
+            // The items the entered name likely is, from the offline search index
+            let template_item = if data.is_null() {
+                String::new()
+            } else {
+                (*(data as *const TarkovPriceOverlayData)).settings.lock().unwrap().template_item.clone()
+            };
+            let suggestions = match &api {
+                Some(api) if !template_item.is_empty() => runtime.block_on(templates.suggest_uids(api.as_ref(), &template_item)),
+                _ => Vec::new(),
+            };
+
+            let template_list = obs_properties_add_list(
+                props,
+                CString::new("template_uid").unwrap().as_ptr(),
+                CString::new("Template Of").unwrap().as_ptr(),
+                OBS_COMBO_TYPE_LIST,
+                OBS_COMBO_FORMAT_STRING,
+            );
+            for (uid, name) in suggestions {
+                obs_property_list_add_string(
+                    template_list,
+                    CString::new(name).unwrap().as_ptr(),
+                    CString::new(uid).unwrap().as_ptr(),
+                );
+            }
+
+            obs_properties_add_path(
+                props,
+                CString::new("template_image").unwrap().as_ptr(),
+                CString::new("Template Image").unwrap().as_ptr(),
+                OBS_PATH_FILE,
+                CString::new("PNG images (*.png)").unwrap().as_ptr(),
+                std::ptr::null(),
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("add_template").unwrap().as_ptr(),
+                CString::new("Add Template").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_add_template_clicked),
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("remove_template").unwrap().as_ptr(),
+                CString::new("Remove Template").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_remove_template_clicked),
+            );
+        }
+
+        if let Some(watchlist) = watchlist {
+            let watched: Vec<String> = watchlist.entries()
+                .iter()
+                .map(|entry| {
+                    let conditions: Vec<String> = entry.conditions.iter().map(|condition| condition.describe()).collect();
+                    format!("{} ({})", entry.name, conditions.join(", "))
+                })
+                .collect();
+            let watchlist_text = if watched.is_empty() {
+                "Watchlist: empty".to_string()
+            } else {
+                format!("Watchlist: {}", watched.join("; "))
+            };
+
+            obs_properties_add_text(
+                props,
+                CString::new("watchlist").unwrap().as_ptr(),
+                CString::new(watchlist_text).unwrap().as_ptr(),
+                OBS_TEXT_INFO,
+            );
+        }
+
+        if let Some(recipes) = recipes {
+            // Skip the count while a refresh holds the book
+            let recipes_text = match recipes.try_read() {
+                Ok(recipes) => format!("Crafts and barters: {}", recipes.len()),
+                Err(_) => "Crafts and barters: updating".to_string(),
+            };
+
+            obs_properties_add_text(
+                props,
+                CString::new("recipes").unwrap().as_ptr(),
+                CString::new(recipes_text).unwrap().as_ptr(),
+                OBS_TEXT_INFO,
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("refresh_recipes").unwrap().as_ptr(),
+                CString::new("Refresh Crafts and Barters").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_refresh_recipes_clicked),
+            );
+        }
+
+        if let Some(needed_items) = needed_items {
+            let requirement_list = obs_properties_add_list(
+                props,
+                CString::new("progress_requirement").unwrap().as_ptr(),
+                CString::new("Quest or Hideout Level").unwrap().as_ptr(),
+                OBS_COMBO_TYPE_LIST,
+                OBS_COMBO_FORMAT_STRING,
+            );
+            for requirement in needed_items.requirements() {
+                let label = if needed_items.is_completed(&requirement.id) {
+                    format!("{} (done)", requirement.label())
+                } else {
+                    requirement.label()
+                };
+                obs_property_list_add_string(
+                    requirement_list,
+                    CString::new(label).unwrap().as_ptr(),
+                    CString::new(requirement.id.as_str()).unwrap().as_ptr(),
+                );
+            }
+
+            obs_properties_add_button(
+                props,
+                CString::new("complete_requirement").unwrap().as_ptr(),
+                CString::new("Mark Completed").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_complete_clicked),
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("reopen_requirement").unwrap().as_ptr(),
+                CString::new("Reopen").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_reopen_clicked),
+            );
+
+            obs_properties_add_text(
+                props,
+                CString::new("collected_item").unwrap().as_ptr(),
+                CString::new("Collected Item").unwrap().as_ptr(),
+                OBS_TEXT_DEFAULT,
+            );
+
+            obs_properties_add_int(
+                props,
+                CString::new("collected_count").unwrap().as_ptr(),
+                CString::new("Collected Count").unwrap().as_ptr(),
+                0,
+                1000,
+                1,
+            );
+
+            obs_properties_add_button(
+                props,
+                CString::new("set_collected").unwrap().as_ptr(),
+                CString::new("Set Collected").unwrap().as_ptr(),
+                Some(tarkov_price_overlay_set_collected_clicked),
+            );
+        }
+        if !data.is_null() {
+            if let Some(api) = api {
+                let stats = api.cache_stats();
+                let stats_text = format!(
+                    "Price cache: {} entries, {} hits, {} misses, {} evictions",
+                    stats.entries, stats.hits, stats.misses, stats.evictions
+                );
+
+                obs_properties_add_text(
+                    props,
+                    CString::new("cache_stats").unwrap().as_ptr(),
+                    CString::new(stats_text).unwrap().as_ptr(),
+                    OBS_TEXT_INFO,
+                );
+
+                let ammo_text = match api.offline_ammo_types() {
+                    Some(0) => "Ammo stats: none, add ammo.json to the data directory".to_string(),
+                    Some(count) => format!("Ammo stats: {} types from ammo.json", count),
+                    None => format!("Ammo stats: from {}", api.name()),
+                };
+
+                obs_properties_add_text(
+                    props,
+                    CString::new("ammo_stats").unwrap().as_ptr(),
+                    CString::new(ammo_text).unwrap().as_ptr(),
+                    OBS_TEXT_INFO,
+                );
+
+                let quota_text = match api.remaining_quota() {
+                    Some(remaining) => format!("Remaining daily API quota: {}", remaining),
+                    None => "Remaining daily API quota: unlimited".to_string(),
+                };
+
+                obs_properties_add_text(
+                    props,
+                    CString::new("api_quota").unwrap().as_ptr(),
+                    CString::new(quota_text).unwrap().as_ptr(),
+                    OBS_TEXT_INFO,
+                );
+            }
+        }
+
         props
     }
 }
@@ -120,8 +991,9 @@ pub extern "C" fn obs_module_load() -> bool {
         create: Some(tarkov_price_overlay_create),
         destroy: Some(tarkov_price_overlay_destroy),
         get_properties: Some(get_properties),
-        update: None,
-        video_render: None,
+        update: Some(tarkov_price_overlay_update),
+        video_tick: Some(tarkov_price_overlay_video_tick),
+        video_render: Some(tarkov_price_overlay_video_render),
         _padding: [0; 20],
     };
 
@@ -129,10 +1001,179 @@ pub extern "C" fn obs_module_load() -> bool {
         obs_register_source(&mut info);
     }
 
-    eprintln!("Tarkov Price Overlay plugin loaded successfully!");
+    let config_path = Config::get_default_config_path();
+    if config_path.exists() {
+        if let Err(e) = Config::load_from_file(&config_path.to_string_lossy()) {
+            log::warn!("Failed to load config from {:?}, using the defaults: {}", config_path, e);
+        }
+    }
+
+    let config = Config::get();
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            log::error!("Failed to start Tarkov Price Overlay runtime: {}", e);
+            return false;
+        }
+    };
+
+    let api = Arc::new(PriceBackend::from_config(&config));
+    log::info!("Using {} prices", api.name());
+
+    let history = match PriceHistory::open(&config.data_dir, config.market_mode) {
+        Ok(history) => {
+            let history = Arc::new(history);
+            let retention = Duration::from_secs(config.history_retention_days as u64 * 24 * 60 * 60);
+            runtime.spawn(prune_price_history(history.clone(), retention));
+            Some(history)
+        }
+        Err(e) => {
+            log::warn!("Price history disabled: {}", e);
+            None
+        }
+    };
+
+    let alerts = match Watchlist::open(&config.data_dir) {
+        Ok(watchlist) => {
+            let alerts = Arc::new(AlertEngine::new(Arc::new(watchlist)));
+            if let Some(url) = &config.alert_webhook_url {
+                runtime.spawn(WebhookSink::new(url).run(alerts.subscribe()));
+            }
+            Some(alerts)
+        }
+        Err(e) => {
+            log::warn!("Price alerts disabled: {}", e);
+            None
+        }
+    };
+    let recipes = match RecipeBook::load(&config.data_dir) {
+        Ok(recipes) => Some(Arc::new(tokio::sync::RwLock::new(recipes))),
+        Err(e) => {
+            log::warn!("Crafts and barters disabled: {:#}", e);
+            None
+        }
+    };
+
+    let needed_items = match NeededItems::open(&config.data_dir) {
+        Ok(needed_items) => Some(Arc::new(needed_items)),
+        Err(e) => {
+            log::warn!("Quest and hideout tracking disabled: {:#}", e);
+            None
+        }
+    };
+    let templates = Arc::new(TemplateManager::new(config.data_dir.join("templates")));
+    let matcher = Arc::new(TemplateMatcher::new(templates.clone(), config.detection_threshold as f64));
+    {
+        let templates = templates.clone();
+        runtime.spawn(async move {
+            if let Err(e) = templates.load_templates().await {
+                log::warn!("Item detection has no templates: {:#}", e);
+            }
+        });
+    }
+    let evict_task = runtime.spawn(evict_cache_entries(api.clone()));
+
+    let mut state = PluginState {
+        runtime,
+        api,
+        evict_task,
+        scheduler: None,
+        history,
+        alerts,
+        recipes,
+        needed_items,
+        templates,
+        matcher,
+        sources: HashMap::new(),
+    };
+    state.start_scheduler(&config);
+    *PLUGIN_STATE.lock().unwrap() = Some(state);
+
+    log::info!("Tarkov Price Overlay plugin loaded successfully!");
     true
 }
 
+// Periodically drops long expired cache entries and logs the cache statistics
+async fn evict_cache_entries(api: Arc<PriceBackend>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+
+        let evicted = api.evict_expired().await;
+        let stats = api.cache_stats();
+        log::info!(
+            "Price cache: {} entries, {} hits, {} misses, {} evictions ({} just now)",
+            stats.entries, stats.hits, stats.misses, stats.evictions, evicted
+        );
+    }
+}
+
+// Downloads the crafts and barters, keeping the current ones on failure.
+// The book is only locked to swap the new recipes in.
+async fn refresh_recipes(recipes: Arc<tokio::sync::RwLock<RecipeBook>>, api: Arc<PriceBackend>, data_dir: PathBuf) {
+    match RecipeBook::fetch(&data_dir, api.as_ref()).await {
+        Ok(fetched) => *recipes.write().await = fetched,
+        Err(e) => log::warn!("Keeping {} crafts and barters: {:#}", recipes.read().await.len(), e),
+    }
+}
+
+// Records every successful price refresh until the scheduler shuts down
+async fn record_price_history(
+    history: Arc<PriceHistory>,
+    mut events: tokio::sync::broadcast::Receiver<PriceEvent>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match events.recv().await {
+            Ok(PriceEvent::Refreshed { items, .. }) => {
+                // Appending to the history file blocks, keep it off the runtime threads
+                let history = history.clone();
+                if let Ok(Err(e)) = tokio::task::spawn_blocking(move || history.record(&items)).await {
+                    log::warn!("Failed to record price history: {}", e);
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+// Drops price history past the retention on startup and once a day after
+async fn prune_price_history(history: Arc<PriceHistory>, retention: Duration) {
+    let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+    loop {
+        interval.tick().await;
+
+        let history = history.clone();
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || history.prune(retention)).await {
+            log::warn!("Failed to prune price history: {}", e);
+        }
+    }
+}
+
+// Checks the watchlist after every successful price refresh
+async fn evaluate_alerts(
+    alerts: Arc<AlertEngine>,
+    mut events: tokio::sync::broadcast::Receiver<PriceEvent>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match events.recv().await {
+            Ok(PriceEvent::Refreshed { items, .. }) => {
+                alerts.evaluate(&items);
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn obs_module_ver() -> u32 {
     0x010000
@@ -171,5 +1212,14 @@ pub extern "C" fn obs_module_set_locale(_locale: *const c_char) {}
 
 #[no_mangle]
 pub extern "C" fn obs_module_unload() {
-    // Cleanup if needed
+    let state = PLUGIN_STATE.lock().unwrap().take();
+    if let Some(PluginState { runtime, api, evict_task, scheduler, history, alerts, .. }) = state {
+        // Stop the refresh task before tearing down the runtime it runs on
+        drop(scheduler);
+        evict_task.abort();
+        drop(api);
+        drop(history);
+        drop(alerts);
+        runtime.shutdown_timeout(Duration::from_secs(2));
+    }
 }
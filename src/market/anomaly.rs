@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use crate::api::Item;
+use crate::config::Config;
+
+/// How far the flea price may stray from its rolling averages before it's
+/// considered suspicious, in percent of the average.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct AnomalyBounds {
+    /// Above either average by more than this is a spike
+    #[serde(default = "default_spike_percent")]
+    pub spike_percent: f64,
+    /// Below either average by more than this is a crash
+    #[serde(default = "default_crash_percent")]
+    pub crash_percent: f64,
+}
+
+fn default_spike_percent() -> f64 {
+    50.0
+}
+
+fn default_crash_percent() -> f64 {
+    40.0
+}
+
+impl Default for AnomalyBounds {
+    fn default() -> Self {
+        Self {
+            spike_percent: default_spike_percent(),
+            crash_percent: default_crash_percent(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceAnomaly {
+    /// Far above the averages, e.g. a manipulated or briefly sold out listing
+    Spike,
+    /// Far below the averages
+    Crash,
+}
+
+impl PriceAnomaly {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PriceAnomaly::Spike => "⚠ spike",
+            PriceAnomaly::Crash => "⚠ crash",
+        }
+    }
+}
+
+/// Flags flea prices that deviate from their 24h or 7 day average beyond the
+/// configured bounds. Checking both catches a spike that has already pulled
+/// the 24h average up.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnomalyClassifier {
+    bounds: AnomalyBounds,
+}
+
+impl AnomalyClassifier {
+    pub fn new(bounds: AnomalyBounds) -> Self {
+        Self { bounds }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.anomaly_bounds)
+    }
+
+    /// `None` for normal prices and for items without a price or averages.
+    pub fn classify(&self, item: &Item) -> Option<PriceAnomaly> {
+        if item.price <= 0 {
+            return None;
+        }
+
+        let deviations: Vec<f64> = [item.avg_24h_price, item.avg_7days_price].iter()
+            .filter(|average| **average > 0)
+            .map(|average| (item.price - *average) as f64 / *average as f64 * 100.0)
+            .collect();
+
+        if deviations.iter().any(|deviation| *deviation > self.bounds.spike_percent) {
+            Some(PriceAnomaly::Spike)
+        } else if deviations.iter().any(|deviation| -*deviation > self.bounds.crash_percent) {
+            Some(PriceAnomaly::Crash)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(price: i32, avg_24h_price: i32, avg_7days_price: i32) -> Item {
+        serde_json::from_value(json!({
+            "uid": "5734758f24597738025ee253",
+            "price": price,
+            "avg_24h_price": avg_24h_price,
+            "avg_7days_price": avg_7days_price,
+        })).unwrap()
+    }
+
+    #[test]
+    fn prices_within_bounds_are_normal() {
+        let classifier = AnomalyClassifier::default();
+        assert_eq!(classifier.classify(&item(10000, 10000, 10000)), None);
+        assert_eq!(classifier.classify(&item(15000, 10000, 10000)), None);
+        assert_eq!(classifier.classify(&item(6000, 10000, 10000)), None);
+    }
+
+    #[test]
+    fn spikes_and_crashes_beyond_the_bounds() {
+        let classifier = AnomalyClassifier::default();
+        assert_eq!(classifier.classify(&item(15001, 10000, 10000)), Some(PriceAnomaly::Spike));
+        assert_eq!(classifier.classify(&item(5999, 10000, 10000)), Some(PriceAnomaly::Crash));
+    }
+
+    #[test]
+    fn either_average_can_flag_a_spike() {
+        // The spike already pulled the 24h average up, the 7 day one still shows it
+        let classifier = AnomalyClassifier::default();
+        assert_eq!(classifier.classify(&item(30000, 25000, 10000)), Some(PriceAnomaly::Spike));
+    }
+
+    #[test]
+    fn missing_prices_are_never_anomalies() {
+        let classifier = AnomalyClassifier::default();
+        assert_eq!(classifier.classify(&item(0, 10000, 10000)), None);
+        assert_eq!(classifier.classify(&item(50000, 0, 0)), None);
+        assert_eq!(classifier.classify(&item(50000, 0, 10000)), Some(PriceAnomaly::Spike));
+    }
+
+    #[test]
+    fn custom_bounds() {
+        let classifier = AnomalyClassifier::new(AnomalyBounds { spike_percent: 10.0, crash_percent: 10.0 });
+        assert_eq!(classifier.classify(&item(11500, 10000, 10000)), Some(PriceAnomaly::Spike));
+        assert_eq!(classifier.classify(&item(8500, 10000, 10000)), Some(PriceAnomaly::Crash));
+    }
+}
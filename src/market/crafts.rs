@@ -0,0 +1,265 @@
+use anyhow::{Result, Context};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use crate::api::{ApiError, ExchangeRates, Item, ItemQuantity, PriceProvider, Recipe, RecipeKind};
+use super::{recommend_sale, FleaFeeCalculator};
+
+const RECIPES_FILE: &str = "recipes.json";
+
+/// Profit of running a recipe once at current prices.
+#[derive(Debug, Clone)]
+pub struct RecipeProfit {
+    pub recipe: Recipe,
+    /// Flea price of all inputs
+    pub input_cost: i64,
+    /// Best sale value (flea after fees or trader) of all outputs
+    pub output_value: i64,
+    pub profit: i64,
+    /// Only for crafts with a known duration
+    pub profit_per_hour: Option<f64>,
+}
+
+/// Hideout crafts and trader barters indexed by input item, snapshotted to
+/// `recipes.json` in the data directory so they're available offline.
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+    // Input uid -> indices into recipes
+    by_input: HashMap<String, Vec<usize>>,
+}
+
+impl RecipeBook {
+    /// Loads the local snapshot, or an empty book if there is none.
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let path = data_dir.as_ref().join(RECIPES_FILE);
+        let recipes = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .context(format!("Failed to read recipes: {:?}", path))?;
+            serde_json::from_str(&contents)
+                .context(format!("Failed to parse recipes: {:?}", path))?
+        } else {
+            Vec::new()
+        };
+
+        info!("Loaded {} crafts and barters", recipes.len());
+        Ok(Self::with_recipes(recipes))
+    }
+
+    fn with_recipes(recipes: Vec<Recipe>) -> Self {
+        let mut by_input: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, recipe) in recipes.iter().enumerate() {
+            let uids: HashSet<&String> = recipe.inputs.iter().map(|input| &input.uid).collect();
+            for uid in uids {
+                by_input.entry(uid.clone()).or_default().push(index);
+            }
+        }
+
+        Self {
+            recipes,
+            by_input,
+        }
+    }
+
+    /// Fetches the provider's recipes and replaces the local snapshot with
+    /// them. The caller swaps the returned book in, so a failure leaves the
+    /// current recipes in place.
+    pub async fn fetch<P: PriceProvider, D: AsRef<Path>>(data_dir: D, provider: &P) -> Result<Self> {
+        let recipes = provider.get_recipes().await
+            .context(format!("Failed to fetch recipes from {}", provider.name()))?;
+
+        let dir = data_dir.as_ref();
+        fs::create_dir_all(dir)
+            .context(format!("Failed to create data directory: {:?}", dir))?;
+
+        let path = dir.join(RECIPES_FILE);
+        let contents = serde_json::to_string(&recipes)?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents)
+            .context(format!("Failed to write recipes: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &path)?;
+
+        info!("Updated {} crafts and barters from {}", recipes.len(), provider.name());
+        Ok(Self::with_recipes(recipes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.recipes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+    }
+
+    /// Recipes the item is an input of.
+    pub fn uses_of(&self, uid: &str) -> Vec<&Recipe> {
+        self.by_input.get(uid)
+            .map(|indices| indices.iter().map(|index| &self.recipes[*index]).collect())
+            .unwrap_or_default()
+    }
+
+    /// The most profitable crafts and barters using the item, at the
+    /// provider's (usually cached) prices. Recipes with an item the provider
+    /// has no price for are skipped.
+    pub async fn best_uses<P: PriceProvider>(
+        &self,
+        provider: &P,
+        uid: &str,
+        fees: &FleaFeeCalculator,
+        rates: &ExchangeRates,
+        limit: usize,
+    ) -> Result<Vec<RecipeProfit>, ApiError> {
+        let recipes = self.uses_of(uid);
+        if recipes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let uids: Vec<String> = recipes.iter()
+            .flat_map(|recipe| recipe.inputs.iter().chain(&recipe.outputs))
+            .map(|quantity| quantity.uid.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let prices = provider.get_items_by_uids(&uids).await?;
+
+        let mut profits: Vec<RecipeProfit> = recipes.into_iter()
+            .filter_map(|recipe| recipe_profit(recipe, &prices, fees, rates))
+            .collect();
+
+        profits.sort_by(|a, b| b.profit.cmp(&a.profit));
+        profits.truncate(limit);
+        Ok(profits)
+    }
+}
+
+fn recipe_profit(
+    recipe: &Recipe,
+    prices: &HashMap<String, Item>,
+    fees: &FleaFeeCalculator,
+    rates: &ExchangeRates,
+) -> Option<RecipeProfit> {
+    let input_cost = total(&recipe.inputs, prices, |item| Some(item.price).filter(|price| *price > 0))?;
+    let output_value = total(&recipe.outputs, prices, |item| recommend_sale(item, fees, rates).map(|sale| sale.price))?;
+    let profit = output_value - input_cost;
+
+    let profit_per_hour = match recipe.kind {
+        RecipeKind::Craft { duration_secs, .. } if duration_secs > 0 => {
+            Some(profit as f64 / (duration_secs as f64 / 3600.0))
+        },
+        _ => None,
+    };
+
+    Some(RecipeProfit {
+        recipe: recipe.clone(),
+        input_cost,
+        output_value,
+        profit,
+        profit_per_hour,
+    })
+}
+
+// Sum of `price * count`, or None if any item has no price
+fn total<F>(quantities: &[ItemQuantity], prices: &HashMap<String, Item>, price: F) -> Option<i64>
+where
+    F: Fn(&Item) -> Option<i32>,
+{
+    quantities.iter()
+        .map(|quantity| {
+            let item = prices.get(&quantity.uid);
+            if item.is_none() {
+                warn!("No price for recipe item {}", quantity.uid);
+            }
+            item.and_then(&price).map(|price| price as i64 * quantity.count as i64)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BOLTS_UID: &str = "57347c5b245977448d35f6e1";
+
+    // Serves fixed prices, for best_uses
+    struct FixedPrices(HashMap<String, Item>);
+
+    impl PriceProvider for FixedPrices {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn search_item(&self, _query: &str) -> Result<Vec<Item>, ApiError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_item_by_uid(&self, uid: &str) -> Result<Item, ApiError> {
+            self.0.get(uid).cloned().ok_or_else(|| ApiError::NotFound(uid.to_string()))
+        }
+
+        async fn get_all_items(&self) -> Result<Vec<Item>, ApiError> {
+            Ok(self.0.values().cloned().collect())
+        }
+    }
+
+    fn prices() -> FixedPrices {
+        let items: Vec<Item> = serde_json::from_value(json!([
+            { "uid": BOLTS_UID, "price": 10000 },
+            // Outputs only sell to traders, so their value is the trader price
+            { "uid": "tushonka", "trader_name": "Jaeger", "trader_price": 15000 },
+            { "uid": "gpu", "trader_name": "Therapist", "trader_price": 50000 },
+        ])).unwrap();
+        FixedPrices(items.into_iter().map(|item| (item.uid.clone(), item)).collect())
+    }
+
+    fn recipe(id: &str, kind: RecipeKind, bolts: u32, output: &str) -> Recipe {
+        Recipe {
+            id: id.to_string(),
+            kind,
+            inputs: vec![ItemQuantity { uid: BOLTS_UID.to_string(), count: bolts }],
+            outputs: vec![ItemQuantity { uid: output.to_string(), count: 1 }],
+        }
+    }
+
+    fn book() -> RecipeBook {
+        let barter = RecipeKind::Barter { trader: "Jaeger".to_string(), level: 1 };
+        let craft = RecipeKind::Craft { station: "Workbench".to_string(), level: 2, duration_secs: 2 * 3600 };
+        RecipeBook::with_recipes(vec![
+            recipe("tushonka", barter, 1, "tushonka"),
+            recipe("gpu", craft, 2, "gpu"),
+            recipe("unpriced", RecipeKind::Barter { trader: "Mechanic".to_string(), level: 2 }, 1, "unknown"),
+        ])
+    }
+
+    async fn best_uses(book: &RecipeBook, uid: &str, limit: usize) -> Vec<RecipeProfit> {
+        book.best_uses(&prices(), uid, &FleaFeeCalculator::default(), &ExchangeRates::default(), limit)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn best_uses_are_ordered_by_profit() {
+        let book = book();
+        let uses = best_uses(&book, BOLTS_UID, 5).await;
+
+        // The recipe with an unpriced output is skipped
+        let ids: Vec<&str> = uses.iter().map(|profit| profit.recipe.id.as_str()).collect();
+        assert_eq!(ids, ["gpu", "tushonka"]);
+        assert_eq!((uses[0].input_cost, uses[0].output_value, uses[0].profit), (20000, 50000, 30000));
+        assert_eq!(uses[0].profit_per_hour, Some(15000.0));
+        assert_eq!(uses[1].profit, 5000);
+        assert_eq!(uses[1].profit_per_hour, None);
+
+        let best = best_uses(&book, BOLTS_UID, 1).await;
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].recipe.id, "gpu");
+    }
+
+    #[tokio::test]
+    async fn items_without_recipes_have_no_uses() {
+        let book = book();
+        assert!(book.uses_of("gpu").is_empty());
+        assert!(best_uses(&book, "gpu", 5).await.is_empty());
+        assert!(best_uses(&RecipeBook::with_recipes(Vec::new()), BOLTS_UID, 5).await.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::api::Item;
+use crate::config::Config;
+
+// Fee rates applied to the base price and to the listing price
+const BASE_PRICE_TAX: f64 = 0.03;
+const LISTING_PRICE_TAX: f64 = 0.03;
+
+// Exponent applied to the price ratio on the side the listing deviates to
+const PRICE_RATIO_EXPONENT: f64 = 1.08;
+
+// Intel center level 3 cuts flea market fees by 30%
+const INTEL_CENTER_DISCOUNT_LEVEL: u8 = 3;
+const INTEL_CENTER_DISCOUNT: f64 = 0.3;
+
+/// Computes the flea market listing fee and what's left of a sale after it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FleaFeeCalculator {
+    intel_center_level: u8,
+}
+
+impl FleaFeeCalculator {
+    pub fn new(intel_center_level: u8) -> Self {
+        Self { intel_center_level }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.intel_center_level)
+    }
+
+    /// Fee for listing `quantity` items with the given base price at
+    /// `listing_price` each.
+    pub fn fee(&self, base_price: i32, listing_price: i32, quantity: u32) -> i64 {
+        if base_price <= 0 || listing_price <= 0 || quantity == 0 {
+            return 0;
+        }
+
+        let base = base_price as f64;
+        let listing = listing_price as f64;
+        let quantity = quantity as f64;
+
+        let mut base_ratio = (base / listing).log10();
+        let mut listing_ratio = (listing / base).log10();
+        if listing < base {
+            base_ratio = base_ratio.powf(PRICE_RATIO_EXPONENT);
+        } else {
+            listing_ratio = listing_ratio.powf(PRICE_RATIO_EXPONENT);
+        }
+
+        let fee = base * BASE_PRICE_TAX * 4f64.powf(base_ratio) * quantity
+            + listing * LISTING_PRICE_TAX * 4f64.powf(listing_ratio) * quantity;
+
+        let discount = if self.intel_center_level >= INTEL_CENTER_DISCOUNT_LEVEL {
+            INTEL_CENTER_DISCOUNT
+        } else {
+            0.0
+        };
+
+        (fee * (1.0 - discount)).round() as i64
+    }
+
+    /// Money in hand after selling `quantity` of the item on the flea at
+    /// `listing_price` each. `None` when the item can't be listed or its base
+    /// price is unknown.
+    pub fn net_proceeds(&self, item: &Item, listing_price: i32, quantity: u32) -> Option<i64> {
+        if item.banned_on_flea || item.base_price <= 0 || listing_price <= 0 {
+            return None;
+        }
+
+        let gross = listing_price as i64 * quantity as i64;
+        Some(gross - self.fee(item.base_price, listing_price, quantity))
+    }
+
+    /// Net proceeds of listing a single item at its current flea price.
+    pub fn net_price(&self, item: &Item) -> Option<i32> {
+        self.net_proceeds(item, item.price, 1).map(|net| net as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fee_at_the_base_price() {
+        let fees = FleaFeeCalculator::default();
+        assert_eq!(fees.fee(10000, 10000, 1), 600);
+        assert_eq!(fees.fee(10000, 10000, 2), 1200);
+    }
+
+    #[test]
+    fn fee_grows_with_the_listing_price() {
+        let fees = FleaFeeCalculator::default();
+        assert_eq!(fees.fee(10000, 20000, 1), 1074);
+        assert_eq!(fees.fee(41090, 33000, 1), 2243);
+        assert!(fees.fee(10000, 5000, 1) < fees.fee(10000, 10000, 1));
+    }
+
+    #[test]
+    fn intel_center_discount_from_level_3() {
+        assert_eq!(FleaFeeCalculator::new(2).fee(10000, 10000, 1), 600);
+        assert_eq!(FleaFeeCalculator::new(3).fee(10000, 10000, 1), 420);
+    }
+
+    #[test]
+    fn no_fee_without_prices_or_quantity() {
+        let fees = FleaFeeCalculator::default();
+        assert_eq!(fees.fee(0, 10000, 1), 0);
+        assert_eq!(fees.fee(10000, 0, 1), 0);
+        assert_eq!(fees.fee(10000, 10000, 0), 0);
+    }
+
+    #[test]
+    fn net_price_subtracts_the_fee() {
+        let fees = FleaFeeCalculator::default();
+        let item: Item = serde_json::from_value(json!({
+            "uid": "5734758f24597738025ee253",
+            "price": 33000,
+            "base_price": 41090,
+        })).unwrap();
+        assert_eq!(fees.net_price(&item), Some(30757));
+
+        let banned = Item { banned_on_flea: true, ..item.clone() };
+        assert_eq!(fees.net_price(&banned), None);
+        let no_base_price = Item { base_price: 0, ..item };
+        assert_eq!(fees.net_price(&no_base_price), None);
+    }
+}
@@ -0,0 +1,13 @@
+mod anomaly;
+mod crafts;
+mod fees;
+mod recommendation;
+mod score;
+mod valuation;
+
+pub use anomaly::{AnomalyBounds, AnomalyClassifier, PriceAnomaly};
+pub use crafts::{RecipeBook, RecipeProfit};
+pub use fees::FleaFeeCalculator;
+pub use recommendation::{recommend_sale, SaleRecommendation, SaleVenue};
+pub use score::{LootScore, ScoreError};
+pub use valuation::{HighlightTier, ItemValuation, ValueBasis};
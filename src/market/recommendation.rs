@@ -0,0 +1,174 @@
+use crate::api::{Currency, ExchangeRates, Item};
+use super::FleaFeeCalculator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaleVenue {
+    Trader(String),
+    Flea,
+}
+
+/// Where an item sells for the most money in hand.
+#[derive(Debug, Clone)]
+pub struct SaleRecommendation {
+    pub venue: SaleVenue,
+    /// Proceeds at the recommended venue in roubles, after the flea fee for
+    /// the flea
+    pub price: i32,
+    /// Currency the venue pays in
+    pub currency: Currency,
+    /// Proceeds in `currency`
+    pub amount: i32,
+    /// How much more the recommended venue pays than the best alternative,
+    /// or the full price when there is no alternative
+    pub margin: i32,
+}
+
+impl SaleRecommendation {
+    pub fn is_trader(&self) -> bool {
+        matches!(self.venue, SaleVenue::Trader(_))
+    }
+}
+
+/// Compares the best trader offer with the flea price after fees, with
+/// dollar and euro offers converted to roubles. Returns `None` when the item
+/// can be sold neither to a trader nor on the flea.
+pub fn recommend_sale(item: &Item, fees: &FleaFeeCalculator, rates: &ExchangeRates) -> Option<SaleRecommendation> {
+    let trader = best_trader_offer(item, rates);
+    let flea = fees.net_price(item).filter(|price| *price > 0);
+
+    match (trader, flea) {
+        (Some(trader), Some(flea_price)) if trader.price_rub >= flea_price => {
+            let margin = trader.price_rub - flea_price;
+            Some(trader.recommend(margin))
+        },
+        (Some(trader), Some(flea_price)) => Some(flea_sale(flea_price, flea_price - trader.price_rub)),
+        (Some(trader), None) => {
+            let margin = trader.price_rub;
+            Some(trader.recommend(margin))
+        },
+        (None, Some(flea_price)) => Some(flea_sale(flea_price, flea_price)),
+        (None, None) => None,
+    }
+}
+
+struct TraderSale {
+    trader_name: String,
+    price: i32,
+    currency: Currency,
+    price_rub: i32,
+}
+
+impl TraderSale {
+    fn recommend(self, margin: i32) -> SaleRecommendation {
+        SaleRecommendation {
+            venue: SaleVenue::Trader(self.trader_name),
+            price: self.price_rub,
+            currency: self.currency,
+            amount: self.price,
+            margin,
+        }
+    }
+}
+
+fn flea_sale(price: i32, margin: i32) -> SaleRecommendation {
+    SaleRecommendation {
+        venue: SaleVenue::Flea,
+        price,
+        currency: Currency::Rub,
+        amount: price,
+        margin,
+    }
+}
+
+// Best offer in roubles among all trader offers, falling back to
+// `trader_name`/`trader_price` for backends that only report the best one
+fn best_trader_offer(item: &Item, rates: &ExchangeRates) -> Option<TraderSale> {
+    item.trader_offers.iter()
+        .max_by_key(|offer| offer.price_rub(rates))
+        .map(|offer| TraderSale {
+            trader_name: offer.trader_name.clone(),
+            price: offer.price,
+            currency: offer.currency,
+            price_rub: offer.price_rub(rates),
+        })
+        .or_else(|| Some(TraderSale {
+            trader_name: item.trader_name.clone(),
+            price: item.trader_price,
+            currency: item.trader_currency,
+            price_rub: item.trader_price_rub(rates),
+        }))
+        .filter(|sale| !sale.trader_name.is_empty() && sale.price_rub > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Flea price of 33,000₽ nets 30,757₽ after the fee
+    fn chain(trader: serde_json::Value) -> Item {
+        let mut item = json!({
+            "uid": "5734758f24597738025ee253",
+            "price": 33000,
+            "base_price": 41090,
+        });
+        item.as_object_mut().unwrap().extend(trader.as_object().unwrap().clone());
+        serde_json::from_value(item).unwrap()
+    }
+
+    fn recommend(item: &Item) -> Option<SaleRecommendation> {
+        recommend_sale(item, &FleaFeeCalculator::default(), &ExchangeRates::default())
+    }
+
+    #[test]
+    fn flea_when_it_nets_more() {
+        let item = chain(json!({ "trader_name": "Therapist", "trader_price": 25476 }));
+        let sale = recommend(&item).unwrap();
+        assert_eq!(sale.venue, SaleVenue::Flea);
+        assert_eq!(sale.price, 30757);
+        assert_eq!(sale.margin, 30757 - 25476);
+    }
+
+    #[test]
+    fn trader_when_it_pays_more_than_the_flea_after_fees() {
+        let item = chain(json!({ "trader_name": "Therapist", "trader_price": 32000 }));
+        let sale = recommend(&item).unwrap();
+        assert_eq!(sale.venue, SaleVenue::Trader("Therapist".to_string()));
+        assert_eq!(sale.price, 32000);
+        assert_eq!(sale.margin, 32000 - 30757);
+    }
+
+    #[test]
+    fn compares_offers_in_roubles() {
+        let item = chain(json!({
+            "trader_offers": [
+                { "trader_name": "Therapist", "price": 25476, "currency": "RUB" },
+                { "trader_name": "Peacekeeper", "price": 250, "currency": "USD" },
+            ],
+        }));
+        let sale = recommend(&item).unwrap();
+        assert_eq!(sale.venue, SaleVenue::Trader("Peacekeeper".to_string()));
+        assert_eq!((sale.amount, sale.currency), (250, Currency::Usd));
+        assert_eq!(sale.price, 250 * 139);
+        assert_eq!(sale.margin, 250 * 139 - 30757);
+    }
+
+    #[test]
+    fn only_venue_gets_the_full_price_as_margin() {
+        let banned = chain(json!({ "trader_name": "Therapist", "trader_price": 25476, "banned_on_flea": true }));
+        let sale = recommend(&banned).unwrap();
+        assert!(sale.is_trader());
+        assert_eq!(sale.margin, 25476);
+
+        let flea_only = chain(json!({}));
+        let sale = recommend(&flea_only).unwrap();
+        assert_eq!(sale.venue, SaleVenue::Flea);
+        assert_eq!(sale.margin, 30757);
+    }
+
+    #[test]
+    fn none_without_any_venue() {
+        let item = chain(json!({ "banned_on_flea": true }));
+        assert!(recommend(&item).is_none());
+    }
+}
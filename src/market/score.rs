@@ -0,0 +1,460 @@
+use thiserror::Error;
+use crate::api::{ExchangeRates, Item};
+use super::FleaFeeCalculator;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ScoreError {
+    #[error("Syntax error at position {position}: {message}")]
+    Syntax { position: usize, message: String },
+    #[error("Unknown variable: {0}")]
+    UnknownVariable(String),
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("Function {name} takes {expected}, got {actual}")]
+    Arity { name: String, expected: String, actual: usize },
+}
+
+/// Item properties usable in a loot score formula.
+pub const VARIABLES: &[&str] = &[
+    "price",
+    "flea_net",
+    "trader_price",
+    "avg_24h_price",
+    "avg_7days_price",
+    "base_price",
+    "slots",
+    "width",
+    "height",
+    "weight",
+    "price_per_slot",
+];
+
+/// A loot score formula such as `max(flea_net, trader_price) / slots`.
+///
+/// Formulas are arithmetic over the item properties in `VARIABLES` with
+/// `+ - * /`, parentheses, numbers and the functions `min`, `max` (any
+/// number of arguments), `abs`, `floor`, `ceil` and `round`. Division by zero
+/// yields 0 so a missing value never produces infinite scores.
+#[derive(Debug, Clone)]
+pub struct LootScore {
+    source: String,
+    expr: Expr,
+}
+
+impl LootScore {
+    pub fn parse(source: &str) -> Result<Self, ScoreError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0, end: source.len() };
+        let expr = parser.expression()?;
+        if let Some((position, token)) = parser.peek() {
+            return Err(ScoreError::Syntax {
+                position: *position,
+                message: format!("unexpected {}", token.describe()),
+            });
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, item: &Item, fees: &FleaFeeCalculator, rates: &ExchangeRates) -> f64 {
+        self.expr.evaluate(item, fees, rates)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Min,
+    Max,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+}
+
+impl Function {
+    fn lookup(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "abs" => Some(Function::Abs),
+            "floor" => Some(Function::Floor),
+            "ceil" => Some(Function::Ceil),
+            "round" => Some(Function::Round),
+            _ => None,
+        }
+    }
+
+    fn check_arity(&self, name: &str, actual: usize) -> Result<(), ScoreError> {
+        let (valid, expected) = match self {
+            Function::Min | Function::Max => (actual >= 1, "at least 1 argument"),
+            _ => (actual == 1, "1 argument"),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ScoreError::Arity {
+                name: name.to_string(),
+                expected: expected.to_string(),
+                actual,
+            })
+        }
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, item: &Item, fees: &FleaFeeCalculator, rates: &ExchangeRates) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(name) => variable(name, item, fees, rates),
+            Expr::Negate(expr) => -expr.evaluate(item, fees, rates),
+            Expr::Binary(left, op, right) => {
+                let left = left.evaluate(item, fees, rates);
+                let right = right.evaluate(item, fees, rates);
+                match op {
+                    BinaryOp::Add => left + right,
+                    BinaryOp::Subtract => left - right,
+                    BinaryOp::Multiply => left * right,
+                    BinaryOp::Divide if right == 0.0 => 0.0,
+                    BinaryOp::Divide => left / right,
+                }
+            },
+            Expr::Call(function, args) => {
+                let mut values = args.iter().map(|arg| arg.evaluate(item, fees, rates));
+                match function {
+                    Function::Min => values.fold(f64::INFINITY, f64::min),
+                    Function::Max => values.fold(f64::NEG_INFINITY, f64::max),
+                    Function::Abs => values.next().unwrap_or(0.0).abs(),
+                    Function::Floor => values.next().unwrap_or(0.0).floor(),
+                    Function::Ceil => values.next().unwrap_or(0.0).ceil(),
+                    Function::Round => values.next().unwrap_or(0.0).round(),
+                }
+            },
+        }
+    }
+}
+
+fn variable(name: &str, item: &Item, fees: &FleaFeeCalculator, rates: &ExchangeRates) -> f64 {
+    match name {
+        "price" => item.price as f64,
+        // Items that can't be sold on the flea are worth nothing there
+        "flea_net" => fees.net_price(item).unwrap_or(0) as f64,
+        // In roubles like every other price
+        "trader_price" => item.trader_price_rub(rates) as f64,
+        "avg_24h_price" => item.avg_24h_price as f64,
+        "avg_7days_price" => item.avg_7days_price as f64,
+        "base_price" => item.base_price as f64,
+        "slots" => item.slots() as f64,
+        "width" => item.width as f64,
+        "height" => item.height as f64,
+        "weight" => item.weight as f64,
+        "price_per_slot" => item.price_per_slot() as f64,
+        // Rejected when parsing
+        _ => 0.0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(value) => format!("number {}", value),
+            Token::Ident(name) => format!("'{}'", name),
+            Token::Plus => "'+'".to_string(),
+            Token::Minus => "'-'".to_string(),
+            Token::Star => "'*'".to_string(),
+            Token::Slash => "'/'".to_string(),
+            Token::LeftParen => "'('".to_string(),
+            Token::RightParen => "')'".to_string(),
+            Token::Comma => "','".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ScoreError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(position, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+            '0'..='9' | '.' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_digit() && c != '.' && c != '_' {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let text = source[position..end].replace('_', "");
+                let value = text.parse().map_err(|_| ScoreError::Syntax {
+                    position,
+                    message: format!("invalid number '{}'", &source[position..end]),
+                })?;
+                tokens.push((position, Token::Number(value)));
+                continue;
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push((position, Token::Ident(source[position..end].to_string())));
+                continue;
+            },
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            other => {
+                return Err(ScoreError::Syntax {
+                    position,
+                    message: format!("unexpected character '{}'", other),
+                });
+            }
+        };
+
+        chars.next();
+        tokens.push((position, token));
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent parser:
+//   expression = term (('+' | '-') term)*
+//   term       = unary (('*' | '/') unary)*
+//   unary      = '-' unary | primary
+//   primary    = number | ident | ident '(' expression (',' expression)* ')' | '(' expression ')'
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    // Source length, reported as the position of errors at the end of input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        if matches!(self.peek(), Some((_, token)) if token == expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ScoreError> {
+        match self.next() {
+            Some((_, token)) if token == expected => Ok(()),
+            Some((position, token)) => Err(ScoreError::Syntax {
+                position,
+                message: format!("expected {}, found {}", expected.describe(), token.describe()),
+            }),
+            None => Err(ScoreError::Syntax {
+                position: self.end,
+                message: format!("expected {}, found end of formula", expected.describe()),
+            }),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, ScoreError> {
+        let mut expr = self.term()?;
+        loop {
+            let op = if self.eat(&Token::Plus) {
+                BinaryOp::Add
+            } else if self.eat(&Token::Minus) {
+                BinaryOp::Subtract
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ScoreError> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat(&Token::Star) {
+                BinaryOp::Multiply
+            } else if self.eat(&Token::Slash) {
+                BinaryOp::Divide
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScoreError> {
+        if self.eat(&Token::Minus) {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ScoreError> {
+        match self.next() {
+            Some((_, Token::Number(value))) => Ok(Expr::Number(value)),
+            Some((_, Token::Ident(name))) => {
+                if !self.eat(&Token::LeftParen) {
+                    if !VARIABLES.contains(&name.as_str()) {
+                        return Err(ScoreError::UnknownVariable(name));
+                    }
+                    return Ok(Expr::Variable(name));
+                }
+
+                let function = Function::lookup(&name)
+                    .ok_or_else(|| ScoreError::UnknownFunction(name.clone()))?;
+
+                let mut args = Vec::new();
+                if !self.eat(&Token::RightParen) {
+                    loop {
+                        args.push(self.expression()?);
+                        if self.eat(&Token::Comma) {
+                            continue;
+                        }
+                        self.expect(Token::RightParen)?;
+                        break;
+                    }
+                }
+
+                function.check_arity(&name, args.len())?;
+                Ok(Expr::Call(function, args))
+            },
+            Some((_, Token::LeftParen)) => {
+                let expr = self.expression()?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            },
+            Some((position, token)) => Err(ScoreError::Syntax {
+                position,
+                message: format!("unexpected {}", token.describe()),
+            }),
+            None => Err(ScoreError::Syntax {
+                position: self.end,
+                message: "unexpected end of formula".to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn evaluate(source: &str) -> f64 {
+        let item: Item = serde_json::from_value(json!({
+            "uid": "5734758f24597738025ee253",
+            "price": 33000,
+            "base_price": 41090,
+            "trader_price": 25476,
+            "width": 1,
+            "height": 2,
+        })).unwrap();
+        LootScore::parse(source).unwrap().evaluate(&item, &FleaFeeCalculator::default(), &ExchangeRates::default())
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4"), 14.0);
+        assert_eq!(evaluate("(2 + 3) * 4"), 20.0);
+        assert_eq!(evaluate("10 - 4 - 3"), 3.0);
+        assert_eq!(evaluate("-2 * -3"), 6.0);
+        assert_eq!(evaluate("1_000 / 4"), 250.0);
+    }
+
+    #[test]
+    fn reads_item_variables() {
+        assert_eq!(evaluate("price / slots"), 16500.0);
+        assert_eq!(evaluate("max(flea_net, trader_price)"), 30757.0);
+        assert_eq!(evaluate("min(price, trader_price, 30000)"), 25476.0);
+        assert_eq!(evaluate("round(weight + 0.4)"), 0.0);
+    }
+
+    #[test]
+    fn division_by_zero_is_zero() {
+        assert_eq!(evaluate("price / (slots - 2)"), 0.0);
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert_eq!(LootScore::parse("prise * 2").unwrap_err(), ScoreError::UnknownVariable("prise".to_string()));
+        assert_eq!(LootScore::parse("sqrt(price)").unwrap_err(), ScoreError::UnknownFunction("sqrt".to_string()));
+    }
+
+    #[test]
+    fn checks_function_arity() {
+        assert_eq!(LootScore::parse("abs(price, slots)").unwrap_err(), ScoreError::Arity {
+            name: "abs".to_string(),
+            expected: "1 argument".to_string(),
+            actual: 2,
+        });
+        assert!(matches!(LootScore::parse("max()"), Err(ScoreError::Arity { actual: 0, .. })));
+    }
+
+    #[test]
+    fn reports_syntax_error_positions() {
+        assert!(matches!(LootScore::parse("price +"), Err(ScoreError::Syntax { position: 7, .. })));
+        assert!(matches!(LootScore::parse("(price * 2"), Err(ScoreError::Syntax { position: 10, .. })));
+        assert!(matches!(LootScore::parse("price ? 2"), Err(ScoreError::Syntax { position: 6, .. })));
+        assert!(matches!(LootScore::parse("price 2"), Err(ScoreError::Syntax { position: 6, .. })));
+        assert!(matches!(LootScore::parse("1.2.3"), Err(ScoreError::Syntax { position: 0, .. })));
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use log::warn;
+use crate::api::{ExchangeRates, Item};
+use crate::config::Config;
+use super::{FleaFeeCalculator, LootScore};
+
+/// What an item's value is measured by for filtering and highlighting when
+/// no loot score formula is configured.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueBasis {
+    /// Flea price of the item
+    #[default]
+    Total,
+    /// Flea price divided by the inventory slots the item takes
+    PerSlot,
+}
+
+/// Highlight color used for items worth at least `min_value`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighlightTier {
+    pub min_value: u32,
+    pub color: [f32; 4],
+}
+
+/// Decides which items are shown, in which color and in which order.
+#[derive(Debug, Clone)]
+pub struct ItemValuation {
+    basis: ValueBasis,
+    min_value: u32,
+    // Sorted by ascending min_value
+    highlight_tiers: Vec<HighlightTier>,
+    // Replaces the value basis when set
+    score: Option<(LootScore, FleaFeeCalculator)>,
+    // Converts dollar and euro trader prices for the score
+    rates: ExchangeRates,
+}
+
+impl ItemValuation {
+    pub fn new(basis: ValueBasis, min_value: u32, mut highlight_tiers: Vec<HighlightTier>) -> Self {
+        highlight_tiers.sort_by_key(|tier| tier.min_value);
+        Self {
+            basis,
+            min_value,
+            highlight_tiers,
+            score: None,
+            rates: ExchangeRates::default(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let valuation = Self::new(config.value_basis, config.min_value, config.highlight_tiers.clone());
+        match config.loot_score.as_deref().filter(|source| !source.trim().is_empty()) {
+            Some(source) => match LootScore::parse(source) {
+                Ok(score) => valuation.with_score(score, FleaFeeCalculator::from_config(config)),
+                Err(e) => {
+                    warn!("Ignoring invalid loot score formula: {}", e);
+                    valuation
+                }
+            },
+            None => valuation,
+        }
+    }
+
+    /// Values items by the loot score formula instead of the value basis.
+    pub fn with_score(mut self, score: LootScore, fees: FleaFeeCalculator) -> Self {
+        self.score = Some((score, fees));
+        self
+    }
+
+    /// Updates the exchange rates the score converts trader prices with.
+    pub fn set_exchange_rates(&mut self, rates: ExchangeRates) {
+        self.rates = rates;
+    }
+
+    pub fn score(&self) -> Option<&LootScore> {
+        self.score.as_ref().map(|(score, _)| score)
+    }
+
+    pub fn value(&self, item: &Item) -> f64 {
+        if let Some((score, fees)) = &self.score {
+            return score.evaluate(item, fees, &self.rates);
+        }
+
+        match self.basis {
+            ValueBasis::Total => item.price as f64,
+            ValueBasis::PerSlot => item.price_per_slot() as f64,
+        }
+    }
+
+    /// Whether the item is worth at least `Config::min_value`.
+    pub fn is_valuable(&self, item: &Item) -> bool {
+        self.value(item) >= self.min_value as f64
+    }
+
+    /// Color of the highest highlight tier the item reaches.
+    pub fn tier_color(&self, item: &Item) -> Option<[f32; 4]> {
+        let value = self.value(item);
+        self.highlight_tiers.iter()
+            .rev()
+            .find(|tier| value >= tier.min_value as f64)
+            .map(|tier| tier.color)
+    }
+}
+
+impl Default for ItemValuation {
+    fn default() -> Self {
+        Self::new(ValueBasis::default(), 0, Vec::new())
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use log::info;
+use crate::api::ItemQuantity;
+
+const REQUIREMENTS_FILE: &str = "requirements.json";
+const PROGRESS_FILE: &str = "progress.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RequirementKind {
+    Quest { name: String },
+    Hideout { station: String, level: u32 },
+}
+
+/// Items a quest or hideout upgrade asks for.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Requirement {
+    pub id: String,
+    pub kind: RequirementKind,
+    pub items: Vec<ItemQuantity>,
+    /// Whether the items have to be found in raid
+    #[serde(default)]
+    pub found_in_raid: bool,
+}
+
+impl Requirement {
+    /// "Quest: Gunsmith - Part 1" or "Hideout: Lavatory 2"
+    pub fn label(&self) -> String {
+        match &self.kind {
+            RequirementKind::Quest { name } => format!("Quest: {}", name),
+            RequirementKind::Hideout { station, level } => format!("Hideout: {} {}", station, level),
+        }
+    }
+}
+
+/// The player's progress, persisted separately from the requirements so the
+/// dataset can be replaced without losing it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Progress {
+    /// Ids of completed quests and built hideout levels
+    #[serde(default)]
+    completed: HashSet<String>,
+    /// Items already set aside, by uid
+    #[serde(default)]
+    collected: HashMap<String, u32>,
+}
+
+/// How many of an item are still needed and for what.
+#[derive(Debug, Clone)]
+pub struct ItemNeed {
+    pub count: u32,
+    pub requirements: Vec<String>,
+    pub found_in_raid: bool,
+}
+
+/// Quest and hideout item requirements with the player's progress.
+///
+/// The requirements are read from `requirements.json` and the progress from
+/// `progress.json`, both in the data directory.
+pub struct NeededItems {
+    progress_path: PathBuf,
+    requirements: Vec<Requirement>,
+    progress: RwLock<Progress>,
+}
+
+impl NeededItems {
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Result<Self> {
+        let requirements_path = data_dir.as_ref().join(REQUIREMENTS_FILE);
+        let requirements: Vec<Requirement> = read_json(&requirements_path)?.unwrap_or_default();
+
+        let progress_path = data_dir.as_ref().join(PROGRESS_FILE);
+        let progress: Progress = read_json(&progress_path)?.unwrap_or_default();
+
+        info!(
+            "Loaded {} quest and hideout requirements, {} completed",
+            requirements.len(),
+            progress.completed.len()
+        );
+        Ok(Self {
+            progress_path,
+            requirements,
+            progress: RwLock::new(progress),
+        })
+    }
+
+    pub fn requirements(&self) -> &[Requirement] {
+        &self.requirements
+    }
+
+    /// Open requirements asking for the item minus what was already
+    /// collected, or `None` when the item isn't needed (anymore).
+    pub fn need(&self, uid: &str) -> Option<ItemNeed> {
+        let progress = self.progress.read().unwrap();
+
+        let mut total = 0;
+        let mut requirements = Vec::new();
+        let mut found_in_raid = false;
+        for requirement in &self.requirements {
+            if progress.completed.contains(&requirement.id) {
+                continue;
+            }
+
+            let count: u32 = requirement.items.iter()
+                .filter(|item| item.uid == uid)
+                .map(|item| item.count)
+                .sum();
+            if count > 0 {
+                total += count;
+                requirements.push(requirement.label());
+                found_in_raid |= requirement.found_in_raid;
+            }
+        }
+
+        let collected = progress.collected.get(uid).copied().unwrap_or(0);
+        let count = total.saturating_sub(collected);
+        if count == 0 {
+            return None;
+        }
+
+        Some(ItemNeed {
+            count,
+            requirements,
+            found_in_raid,
+        })
+    }
+
+    pub fn is_needed(&self, uid: &str) -> bool {
+        self.need(uid).is_some()
+    }
+
+    pub fn is_completed(&self, requirement_id: &str) -> bool {
+        self.progress.read().unwrap().completed.contains(requirement_id)
+    }
+
+    /// Marks a quest or hideout level as done and saves the progress.
+    pub fn complete(&self, requirement_id: &str) -> Result<()> {
+        self.progress.write().unwrap().completed.insert(requirement_id.to_string());
+        self.save()
+    }
+
+    /// Undoes `complete`.
+    pub fn reopen(&self, requirement_id: &str) -> Result<()> {
+        self.progress.write().unwrap().completed.remove(requirement_id);
+        self.save()
+    }
+
+    /// Sets how many of an item were already set aside and saves the progress.
+    pub fn set_collected(&self, uid: &str, count: u32) -> Result<()> {
+        {
+            let mut progress = self.progress.write().unwrap();
+            if count == 0 {
+                progress.collected.remove(uid);
+            } else {
+                progress.collected.insert(uid.to_string(), count);
+            }
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let contents = serde_json::to_string_pretty(&*self.progress.read().unwrap())?;
+
+        if let Some(dir) = self.progress_path.parent() {
+            fs::create_dir_all(dir)
+                .context(format!("Failed to create data directory: {:?}", dir))?;
+        }
+
+        let tmp_path = self.progress_path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents)
+            .context(format!("Failed to write progress: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.progress_path)?;
+        Ok(())
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)
+        .context(format!("Failed to read {:?}", path))?;
+    let value = serde_json::from_str(&contents)
+        .context(format!("Failed to parse {:?}", path))?;
+    Ok(Some(value))
+}
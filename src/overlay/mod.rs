@@ -1,13 +1,70 @@
 use anyhow::{Result, Context};
 use obs_sys::{obs_source_t, gs_effect_t};
 use opencv::core::{Rect, Scalar};
-use crate::api::Item;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tokio::sync::mpsc;
+use crate::alerts::PriceAlert;
+use crate::api::{ApiError, Currency, ExchangeRates, Item, MarketMode, PriceProvider};
+use crate::config::Config;
+use crate::history::{PriceHistory, Trend};
+use crate::market::{recommend_sale, AnomalyClassifier, FleaFeeCalculator, ItemValuation, RecipeBook, RecipeProfit, SaleVenue};
+use crate::needs::NeededItems;
+use crate::scheduler::PriceEvent;
 use log::{info, warn, error};
 
+// Window the tooltip trend arrow is computed over
+const TREND_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Alert toasts shown at once, older ones are dropped
+const MAX_ALERT_TOASTS: usize = 4;
+
+/// Items found in a frame, or why they couldn't be priced.
+pub type DetectionResult = Result<Detections, ApiError>;
+
+#[derive(Debug, Default)]
+pub struct Detections {
+    pub items: Vec<(Rect, Item)>,
+    /// Most profitable craft or barter using each item, by uid
+    pub best_uses: HashMap<String, RecipeProfit>,
+}
+
 pub struct OverlayRenderer {
     highlight_color: [f32; 4],
     tooltip_font_size: f32,
     tooltip_font_color: [f32; 4],
+    price_history: Option<Arc<PriceHistory>>,
+    market_mode: MarketMode,
+    // Language item names are shown in
+    language: String,
+    // Set when tooltips show the price after the flea fee
+    fee_calculator: Option<FleaFeeCalculator>,
+    // Set when tooltips recommend a trader that beats the flea
+    sale_fees: Option<FleaFeeCalculator>,
+    valuation: ItemValuation,
+    // Items still needed for quests or the hideout and their highlight color
+    needed_items: Option<(Arc<NeededItems>, [f32; 4])>,
+    exchange_rates: ExchangeRates,
+    display_currency: Currency,
+    // Add the rouble value to dollar and euro prices
+    show_rouble_equivalent: bool,
+    // Watchlist alerts on screen, oldest first, with the time they arrived
+    alert_toasts: VecDeque<(Instant, PriceAlert)>,
+    alert_duration: Duration,
+    alerts: Option<broadcast::Receiver<PriceAlert>>,
+    // Set when suspicious flea prices are marked in tooltips
+    anomaly_classifier: Option<AnomalyClassifier>,
+    highlight_enabled: bool,
+    tooltip_enabled: bool,
+    // Refresh results, to show the error of a failed refresh
+    price_events: Option<broadcast::Receiver<PriceEvent>>,
+    error: Option<ApiError>,
+    // Valuable items of the latest detection, drawn every frame
+    detections: Vec<(Rect, Item)>,
+    best_uses: HashMap<String, RecipeProfit>,
+    detection_results: Option<mpsc::UnboundedReceiver<DetectionResult>>,
 }
 
 impl OverlayRenderer {
@@ -16,23 +73,314 @@ impl OverlayRenderer {
             highlight_color,
             tooltip_font_size,
             tooltip_font_color,
+            price_history: None,
+            market_mode: MarketMode::default(),
+            language: "en".to_string(),
+            fee_calculator: None,
+            sale_fees: None,
+            valuation: ItemValuation::default(),
+            needed_items: None,
+            exchange_rates: ExchangeRates::default(),
+            display_currency: Currency::Rub,
+            show_rouble_equivalent: true,
+            alert_toasts: VecDeque::new(),
+            alert_duration: Duration::from_secs(8),
+            alerts: None,
+            anomaly_classifier: None,
+            highlight_enabled: true,
+            tooltip_enabled: true,
+            price_events: None,
+            error: None,
+            detections: Vec::new(),
+            best_uses: HashMap::new(),
+            detection_results: None,
+        }
+    }
+
+    /// Creates a renderer with the display settings and tooltip features
+    /// enabled in the config.
+    pub fn from_config(config: &Config) -> Self {
+        let mut renderer = Self::new(config.highlight_color, config.tooltip_font_size as f32, config.tooltip_font_color);
+        renderer.highlight_enabled = config.highlight_enabled;
+        renderer.tooltip_enabled = config.tooltip_enabled;
+        renderer.set_market_mode(config.market_mode);
+        renderer.set_language(&config.display_language);
+        renderer.set_valuation(ItemValuation::from_config(config));
+        renderer.set_alert_duration(Duration::from_secs(config.alert_toast_secs as u64));
+        renderer.set_display_currency(config.display_currency, config.show_rouble_equivalent);
+
+        let fees = FleaFeeCalculator::from_config(config);
+        renderer.set_net_price(Some(fees).filter(|_| config.show_net_price));
+        renderer.set_sale_recommendation(Some(fees).filter(|_| config.show_sale_recommendation));
+        if config.flag_price_anomalies {
+            renderer.set_anomaly_classifier(Some(AnomalyClassifier::from_config(config)));
+        }
+        renderer
+    }
+
+    /// Follows the refreshes of the price refresh scheduler, showing the
+    /// error of a failed refresh until the next one succeeds and taking the
+    /// exchange rates from every refreshed price list.
+    pub fn subscribe_prices(&mut self, price_events: broadcast::Receiver<PriceEvent>) {
+        self.price_events = Some(price_events);
+    }
+
+    /// Shows the alerts of the watchlist as toasts, see `push_alert`.
+    pub fn subscribe_alerts(&mut self, alerts: broadcast::Receiver<PriceAlert>) {
+        self.alerts = Some(alerts);
+    }
+
+    /// Shows an error instead of the detections, or clears it when `None`.
+    pub fn set_error(&mut self, error: Option<ApiError>) {
+        self.error = error;
+    }
+
+    /// Replaces the items drawn every frame with the resolved matches of the
+    /// latest detection, see `resolve_matches`. Items below the minimum
+    /// value are dropped.
+    pub fn set_detections(&mut self, detections: Vec<(Rect, Item)>) {
+        self.detections = self.filter_valuable(detections);
+    }
+
+    /// Draws the results of a `capture::FrameDetection`, see `set_detections`.
+    /// A failed lookup is shown like a failed refresh.
+    pub fn receive_detections(&mut self, detection_results: mpsc::UnboundedReceiver<DetectionResult>) {
+        self.detection_results = Some(detection_results);
+    }
+
+    // Takes the latest detection that arrived since the last frame
+    fn poll_detections(&mut self) {
+        let Some(detection_results) = &mut self.detection_results else {
+            return;
+        };
+
+        let mut latest = None;
+        loop {
+            match detection_results.try_recv() {
+                Ok(result) => latest = Some(result),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    self.detection_results = None;
+                    break;
+                },
+            }
+        }
+
+        match latest {
+            Some(Ok(detections)) => {
+                self.set_detections(detections.items);
+                self.best_uses = detections.best_uses;
+            },
+            Some(Err(error)) => self.error = Some(error),
+            None => {},
+        }
+    }
+
+    // Takes the refresh results that arrived since the last frame without
+    // blocking the render thread
+    fn poll_price_events(&mut self) {
+        let Some(price_events) = &mut self.price_events else {
+            return;
+        };
+
+        let mut exchange_rates = None;
+        loop {
+            match price_events.try_recv() {
+                Ok(PriceEvent::Refreshed { items, .. }) => {
+                    self.error = None;
+                    exchange_rates = Some(ExchangeRates::from_items(items.iter()));
+                },
+                Ok(PriceEvent::RefreshFailed(error)) => self.error = Some(error),
+                Err(TryRecvError::Lagged(_)) => {},
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => {
+                    self.price_events = None;
+                    break;
+                },
+            }
+        }
+
+        if let Some(exchange_rates) = exchange_rates {
+            self.set_exchange_rates(exchange_rates);
+        }
+    }
+
+    fn poll_alerts(&mut self) {
+        let Some(alerts) = &mut self.alerts else {
+            return;
+        };
+
+        let mut arrived = Vec::new();
+        loop {
+            match alerts.try_recv() {
+                Ok(alert) => arrived.push(alert),
+                Err(TryRecvError::Lagged(_)) => {},
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => {
+                    self.alerts = None;
+                    break;
+                },
+            }
+        }
+        for alert in arrived {
+            self.push_alert(alert);
+        }
+    }
+
+    /// Draws one frame: the error of the last failed request, or else the
+    /// highlights and tooltips of the detected items.
+    pub fn render(&mut self, source: *mut obs_source_t) {
+        self.poll_price_events();
+        self.poll_detections();
+        self.poll_alerts();
+        self.draw_alerts(source);
+
+        if let Some(error) = &self.error {
+            self.draw_error(source, error);
+            return;
+        }
+
+        for (rect, item) in &self.detections {
+            if self.highlight_enabled {
+                self.draw_highlight(source, rect, item);
+            }
+            if self.tooltip_enabled {
+                self.draw_tooltip(source, rect, item);
+            }
         }
     }
 
+    /// Enables the 24h trend arrow in tooltips.
+    pub fn set_price_history(&mut self, price_history: Arc<PriceHistory>) {
+        self.price_history = Some(price_history);
+    }
+
+    /// Sets the market shown in the tooltip header, so viewers can tell PvE
+    /// from PvP prices.
+    pub fn set_market_mode(&mut self, market_mode: MarketMode) {
+        self.market_mode = market_mode;
+    }
+
+    /// Shows item names in `language` when the item has a name in it.
+    pub fn set_language(&mut self, language: &str) {
+        self.language = language.to_string();
+    }
+
+    /// Shows the price after the flea market fee instead of the listing
+    /// price, or the listing price again when `None`.
+    pub fn set_net_price(&mut self, fee_calculator: Option<FleaFeeCalculator>) {
+        self.fee_calculator = fee_calculator;
+    }
+
+    /// Adds "Sell to <trader>" to tooltips of items a trader pays more for
+    /// than the flea after fees, or disables it when `None`.
+    pub fn set_sale_recommendation(&mut self, fees: Option<FleaFeeCalculator>) {
+        self.sale_fees = fees;
+    }
+
+    /// Sets the minimum value and the value based highlight colors.
+    pub fn set_valuation(&mut self, mut valuation: ItemValuation) {
+        valuation.set_exchange_rates(self.exchange_rates);
+        self.valuation = valuation;
+    }
+
+    /// Updates the rates dollar and euro prices are converted with, usually
+    /// from `PriceProvider::get_exchange_rates` after a refresh.
+    pub fn set_exchange_rates(&mut self, rates: ExchangeRates) {
+        self.exchange_rates = rates;
+        self.valuation.set_exchange_rates(rates);
+    }
+
+    /// Shows flea prices in the given currency, optionally followed by their
+    /// rouble value. Trader prices are always shown in the currency the
+    /// trader pays in.
+    pub fn set_display_currency(&mut self, currency: Currency, show_rouble_equivalent: bool) {
+        self.display_currency = currency;
+        self.show_rouble_equivalent = show_rouble_equivalent;
+    }
+
+    /// Highlights items still needed for quests or the hideout in their own
+    /// color regardless of their value, and adds the needed count to tooltips.
+    pub fn set_needed_items(&mut self, needed_items: Arc<NeededItems>, highlight_color: [f32; 4]) {
+        self.needed_items = Some((needed_items, highlight_color));
+    }
+
+    /// Marks flea prices far from their averages, e.g. "⚠ spike", or
+    /// disables it when `None`.
+    pub fn set_anomaly_classifier(&mut self, anomaly_classifier: Option<AnomalyClassifier>) {
+        self.anomaly_classifier = anomaly_classifier;
+    }
+
+    /// How long alert toasts stay on screen.
+    pub fn set_alert_duration(&mut self, alert_duration: Duration) {
+        self.alert_duration = alert_duration;
+    }
+
+    /// Queues a watchlist alert to be shown as a toast by `draw_alerts`.
+    pub fn push_alert(&mut self, alert: PriceAlert) {
+        let alert_duration = self.alert_duration;
+        self.alert_toasts.retain(|(arrived, _)| arrived.elapsed() < alert_duration);
+        self.alert_toasts.push_back((Instant::now(), alert));
+        while self.alert_toasts.len() > MAX_ALERT_TOASTS {
+            self.alert_toasts.pop_front();
+        }
+    }
+
+    // "33,000₽", or "$237 (33,000₽)" with the rouble equivalent enabled
+    fn format_money(&self, amount: i32, currency: Currency) -> String {
+        let text = currency.format(amount as i64);
+        if currency == Currency::Rub || !self.show_rouble_equivalent {
+            return text;
+        }
+
+        let roubles = self.exchange_rates.to_roubles(amount, currency);
+        format!("{} ({})", text, Currency::Rub.format(roubles as i64))
+    }
+
+    fn is_needed(&self, item: &Item) -> bool {
+        self.needed_items.as_ref().map_or(false, |(needed_items, _)| needed_items.is_needed(&item.uid))
+    }
+
+    /// Drops matches worth less than the configured minimum value, unless
+    /// they're still needed, and orders the rest by ascending value so the
+    /// most valuable items are drawn last and end up on top.
+    pub fn filter_valuable(&self, matches: Vec<(Rect, Item)>) -> Vec<(Rect, Item)> {
+        let mut matches: Vec<(Rect, Item, f64)> = matches.into_iter()
+            .filter(|(_, item)| self.valuation.is_valuable(item) || self.is_needed(item))
+            .map(|(rect, item)| {
+                let value = self.valuation.value(&item);
+                (rect, item, value)
+            })
+            .collect();
+
+        matches.sort_by(|a, b| a.2.total_cmp(&b.2));
+        matches.into_iter().map(|(rect, item, _)| (rect, item)).collect()
+    }
+
     pub fn update_settings(&mut self, highlight_color: [f32; 4], tooltip_font_size: f32, tooltip_font_color: [f32; 4]) {
         self.highlight_color = highlight_color;
         self.tooltip_font_size = tooltip_font_size;
         self.tooltip_font_color = tooltip_font_color;
     }
 
-    pub fn draw_highlight(&self, source: *mut obs_source_t, rect: &Rect) {
+    pub fn draw_highlight(&self, source: *mut obs_source_t, rect: &Rect, item: &Item) {
         unsafe {
+            // Needed items stand out over value tiers
+            let needed_color = self.needed_items.as_ref()
+                .filter(|(needed_items, _)| needed_items.is_needed(&item.uid))
+                .map(|(_, color)| *color);
+
+            // Tier color if the item reaches a highlight tier
+            let highlight_color = needed_color
+                .or_else(|| self.valuation.tier_color(item))
+                .unwrap_or(self.highlight_color);
+
             // Convert our color from [0-1] to OBS GS format
             let color = gs_color_from_rgba(
-                (self.highlight_color[0] * 255.0) as u8,
-                (self.highlight_color[1] * 255.0) as u8,
-                (self.highlight_color[2] * 255.0) as u8,
-                (self.highlight_color[3] * 255.0) as u8,
+                (highlight_color[0] * 255.0) as u8,
+                (highlight_color[1] * 255.0) as u8,
+                (highlight_color[2] * 255.0) as u8,
+                (highlight_color[3] * 255.0) as u8,
             );
             
             // Draw a rectangle using OBS graphics API
@@ -44,16 +392,87 @@ impl OverlayRenderer {
                 color,
                 2, // Border thickness
             );
+
+            // Short name label in the top left corner of the box
+            draw_text(
+                item.display_short_name(&self.language),
+                rect.x + 3,
+                rect.y + self.tooltip_font_size as i32,
+                self.tooltip_font_size,
+                color,
+            );
         }
     }
 
     pub fn draw_tooltip(&self, source: *mut obs_source_t, rect: &Rect, item: &Item) {
         unsafe {
-            // Format price with thousand separators
-            let price_text = format_price(item.price);
+            // Net price if enabled and computable, otherwise the listing price
+            let net_price = self.fee_calculator.and_then(|calculator| calculator.net_price(item));
+
+            // Flea prices are in roubles, convert to the display currency
+            let price = self.exchange_rates.convert(net_price.unwrap_or(item.price), Currency::Rub, self.display_currency);
+            let price_text = self.format_money(price, self.display_currency);
             
             // Format the tooltip text
-            let tooltip_text = format!("{}: {}₽", item.name, price_text);
+            let mut tooltip_text = format!("[{}] {}: {}", self.market_mode.label(), item.display_name(&self.language), price_text);
+            if net_price.is_some() {
+                tooltip_text.push_str(" net");
+            }
+
+            // Don't present a likely manipulated price as the real one
+            if let Some(anomaly) = self.anomaly_classifier.and_then(|classifier| classifier.classify(item)) {
+                tooltip_text.push_str(&format!(" {}", anomaly.label()));
+                if item.avg_24h_price > 0 {
+                    let average = self.exchange_rates.convert(item.avg_24h_price, Currency::Rub, self.display_currency);
+                    tooltip_text.push_str(&format!(" (24h avg {})", self.display_currency.format(average as i64)));
+                }
+            }
+
+            // Show the loot score if one is configured
+            if self.valuation.score().is_some() {
+                tooltip_text.push_str(&format!(" | Score: {}", format_price(self.valuation.value(item).round() as i32)));
+            }
+
+            // Compact ballistics for ammo, noting when they aren't known
+            if item.is_ammo() {
+                match &item.ammo {
+                    Some(ammo) => tooltip_text.push_str(&format!(" | {}", ammo.summary())),
+                    None => tooltip_text.push_str(" | Ballistics unknown"),
+                }
+            }
+
+            // Remaining quest/hideout need, e.g. "Needed: 2 FiR"
+            if let Some((needed_items, _)) = &self.needed_items {
+                if let Some(need) = needed_items.need(&item.uid) {
+                    tooltip_text.push_str(&format!(" | Needed: {}", need.count));
+                    if need.found_in_raid {
+                        tooltip_text.push_str(" FiR");
+                    }
+                }
+            }
+
+            // The craft or barter that turns the item into the most profit
+            if let Some(best_use) = self.best_uses.get(&item.uid).filter(|best_use| best_use.profit > 0) {
+                tooltip_text.push_str(&format!(" | {}: +{}", best_use.recipe.source(), Currency::Rub.format(best_use.profit)));
+            }
+
+            // Point out when a trader beats the flea
+            if let Some(fees) = &self.sale_fees {
+                if let Some(recommendation) = recommend_sale(item, fees, &self.exchange_rates) {
+                    if let SaleVenue::Trader(trader_name) = &recommendation.venue {
+                        let price_text = self.format_money(recommendation.amount, recommendation.currency);
+                        tooltip_text.push_str(&format!(" | Sell to {}: {}", trader_name, price_text));
+                    }
+                }
+            }
+
+            // Append the 24h trend if we have history for the item
+            if let Some(history) = &self.price_history {
+                if let Some(change) = history.percent_change(&item.uid, TREND_WINDOW) {
+                    let trend = history.trend(&item.uid, TREND_WINDOW).unwrap_or(Trend::Flat);
+                    tooltip_text.push_str(&format!(" {} {:+.1}%", trend.arrow(), change));
+                }
+            }
             
             // Convert our color from [0-1] to OBS GS format
             let color = gs_color_from_rgba(
@@ -80,6 +499,81 @@ impl OverlayRenderer {
             );
         }
     }
+
+    /// Draws the alert toasts that haven't timed out yet, newest at the top,
+    /// below where `draw_error` draws.
+    pub fn draw_alerts(&self, source: *mut obs_source_t) {
+        unsafe {
+            let color = gs_color_from_rgba(255, 200, 0, 255);
+
+            let toasts = self.alert_toasts.iter()
+                .rev()
+                .filter(|(arrived, _)| arrived.elapsed() < self.alert_duration);
+            for (index, (_, alert)) in toasts.enumerate() {
+                let message = format!("Alert: {}", alert.message);
+                let y = 50 + index as i32 * 35;
+
+                draw_tooltip_background(10, y, message.len() as u32 * 10, 30);
+                draw_text(&message, 15, y + 20, self.tooltip_font_size, color);
+            }
+        }
+    }
+
+    /// Draws a short description of an API error in the top left corner so
+    /// problems like an invalid API key don't just look like "no items".
+    pub fn draw_error(&self, source: *mut obs_source_t, error: &ApiError) {
+        unsafe {
+            let message = format!("Tarkov Price Overlay: {}", error.user_message());
+
+            let color = gs_color_from_rgba(255, 80, 80, 255);
+
+            draw_tooltip_background(10, 10, message.len() as u32 * 10, 30);
+            draw_text(&message, 15, 30, self.tooltip_font_size, color);
+        }
+    }
+}
+
+/// Resolves template matches (rect, item uid) into drawable items using the
+/// given price provider, with a single batched lookup for the whole frame.
+/// Matches whose item is unknown are skipped. Errors are returned so they can
+/// be shown with `draw_error`.
+pub async fn resolve_matches<P: PriceProvider>(
+    provider: &P,
+    matches: &[(Rect, String)],
+) -> Result<Vec<(Rect, Item)>, ApiError> {
+    let uids: Vec<String> = matches.iter().map(|(_, item_uid)| item_uid.clone()).collect();
+    let items = provider.get_items_by_uids(&uids).await?;
+
+    Ok(matches.iter()
+        .filter_map(|(rect, item_uid)| {
+            let item = items.get(item_uid).cloned();
+            if item.is_none() {
+                warn!("No price for item {} from {}", item_uid, provider.name());
+            }
+            item.map(|item| (*rect, item))
+        })
+        .collect())
+}
+
+/// Most profitable craft or barter using each resolved item, at the
+/// provider's cached prices. Items whose uses can't be priced are left out.
+pub async fn best_uses<P: PriceProvider>(
+    provider: &P,
+    recipes: &RecipeBook,
+    items: &[(Rect, Item)],
+    fees: &FleaFeeCalculator,
+) -> HashMap<String, RecipeProfit> {
+    let rates = provider.get_exchange_rates().await.unwrap_or_default();
+    let uids: HashSet<&String> = items.iter().map(|(_, item)| &item.uid).collect();
+
+    let mut best_uses = HashMap::new();
+    for uid in uids {
+        match recipes.best_uses(provider, uid, fees, &rates, 1).await {
+            Ok(uses) => best_uses.extend(uses.into_iter().next().map(|best_use| (uid.clone(), best_use))),
+            Err(e) => warn!("Failed to price the uses of item {}: {}", uid, e),
+        }
+    }
+    best_uses
 }
 
 // Helper function to format price with thousand separators
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use log::{info, warn};
+use crate::api::{ApiError, Item, PriceProvider};
+
+/// Shortest refresh interval; shorter ones, including a 0 from a broken
+/// config, are raised to it so the refresh loop can't spin.
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct PriceChange {
+    pub uid: String,
+    pub name: String,
+    pub old_price: i32,
+    pub new_price: i32,
+}
+
+impl PriceChange {
+    pub fn percent_change(&self) -> f64 {
+        if self.old_price == 0 {
+            return 0.0;
+        }
+        (self.new_price - self.old_price) as f64 / self.old_price as f64 * 100.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PriceEvent {
+    /// A refresh completed. `changes` is empty on the first refresh, which
+    /// only establishes the baseline.
+    Refreshed {
+        items: Arc<Vec<Item>>,
+        changes: Vec<PriceChange>,
+    },
+    RefreshFailed(ApiError),
+}
+
+/// Periodically refreshes the full item list from a price provider and
+/// publishes the price changes to subscribers such as the overlay.
+pub struct PriceRefreshScheduler {
+    events: broadcast::Sender<PriceEvent>,
+    interval: watch::Sender<Duration>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl PriceRefreshScheduler {
+    /// Spawns the refresh task on the current tokio runtime. The first refresh
+    /// happens immediately.
+    pub fn start<P: PriceProvider + 'static>(provider: Arc<P>, interval: Duration) -> Self {
+        let interval = interval.max(MIN_REFRESH_INTERVAL);
+        let (events, _) = broadcast::channel(16);
+        let (interval_tx, interval_rx) = watch::channel(interval);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        tokio::spawn(run_refresh_loop(provider, events.clone(), interval_rx, shutdown_rx));
+        info!("Price refresh scheduler started with an interval of {:?}", interval);
+
+        Self {
+            events,
+            interval: interval_tx,
+            shutdown: shutdown_tx,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PriceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Changes the refresh interval. The next refresh is rescheduled to the
+    /// new interval after the last one, setting the same interval again does
+    /// nothing.
+    pub fn set_interval(&self, interval: Duration) {
+        let interval = interval.max(MIN_REFRESH_INTERVAL);
+        self.interval.send_if_modified(|current| {
+            let changed = *current != interval;
+            *current = interval;
+            changed
+        });
+    }
+
+    /// Asks the refresh task to stop; an in-flight refresh is abandoned.
+    pub fn stop(&self) {
+        let _ = self.shutdown.send(true);
+    }
+}
+
+impl Drop for PriceRefreshScheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn run_refresh_loop<P: PriceProvider>(
+    provider: Arc<P>,
+    events: broadcast::Sender<PriceEvent>,
+    mut interval: watch::Receiver<Duration>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut previous_prices: HashMap<String, i32> = HashMap::new();
+
+    'refresh: loop {
+        let started_at = tokio::time::Instant::now();
+        let result = tokio::select! {
+            result = provider.refresh_all_items() => result,
+            _ = shutdown.changed() => break,
+        };
+
+        match result {
+            Ok(items) => {
+                let changes = diff_prices(&previous_prices, &items);
+                info!("Refreshed {} items from {}, {} price changes", items.len(), provider.name(), changes.len());
+
+                previous_prices = items.iter().map(|item| (item.uid.clone(), item.price)).collect();
+                let _ = events.send(PriceEvent::Refreshed {
+                    items: Arc::new(items),
+                    changes,
+                });
+            },
+            Err(e) => {
+                warn!("Price refresh from {} failed: {}", provider.name(), e);
+                let _ = events.send(PriceEvent::RefreshFailed(e));
+            }
+        }
+
+        // An interval change only moves the next refresh, counted from the
+        // start of the last one
+        loop {
+            let next_refresh = started_at + *interval.borrow_and_update();
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_refresh) => break,
+                changed = interval.changed() => {
+                    if changed.is_err() {
+                        break 'refresh;
+                    }
+                },
+                _ = shutdown.changed() => break 'refresh,
+            }
+        }
+    }
+
+    info!("Price refresh scheduler stopped");
+}
+
+fn diff_prices(previous_prices: &HashMap<String, i32>, items: &[Item]) -> Vec<PriceChange> {
+    items.iter()
+        .filter_map(|item| {
+            let old_price = *previous_prices.get(&item.uid)?;
+            if old_price == item.price {
+                return None;
+            }
+
+            Some(PriceChange {
+                uid: item.uid.clone(),
+                name: item.name.clone(),
+                old_price,
+                new_price: item.price,
+            })
+        })
+        .collect()
+}
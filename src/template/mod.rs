@@ -9,8 +9,8 @@ use std::collections::HashMap;
 use std::fs;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use log::{info, warn, error};
-use crate::api::{TarkovMarketAPI, Item};
+use log::{info, error};
+use crate::api::PriceProvider;
 
 pub struct TemplateManager {
     template_dir: PathBuf,
@@ -49,7 +49,7 @@ impl TemplateManager {
             }
 
             // Skip non-image files (we only support PNG for templates)
-            if path.extension().map_or(false, |ext| ext == "png") {
+            if path.extension().is_some_and(|ext| ext == "png") {
                 let filename = path.file_stem()
                     .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?
                     .to_string_lossy()
@@ -59,10 +59,7 @@ impl TemplateManager {
                 let item_uid = filename;
 
                 // Load the template
-                let template = imgcodecs::imread(
-                    path.to_str().unwrap(),
-                    imgcodecs::IMREAD_GRAYSCALE,
-                )?;
+                let template = read_template(&path)?;
 
                 // Store the template
                 templates.insert(item_uid, template);
@@ -74,9 +71,10 @@ impl TemplateManager {
         Ok(())
     }
 
-    pub async fn download_templates(&self, api: &TarkovMarketAPI) -> Result<usize> {
-        // Get all items from the API
-        let items = api.get_all_items().await?;
+    pub async fn download_templates<P: PriceProvider>(&self, provider: &P) -> Result<usize> {
+        // Get all items from the price provider
+        let items = provider.get_all_items().await?;
+        info!("Fetched {} items from {}", items.len(), provider.name());
         let mut downloaded_count = 0;
 
         // Create directory if it doesn't exist
@@ -112,7 +110,7 @@ impl TemplateManager {
                     downloaded_count += 1;
 
                     // Load the template into memory
-                    match imgcodecs::imread(template_path.to_str().unwrap(), imgcodecs::IMREAD_GRAYSCALE) {
+                    match read_template(&template_path) {
                         Ok(template) => {
                             let mut templates = self.templates.write().await;
                             templates.insert(item.uid, template);
@@ -136,42 +134,13 @@ impl TemplateManager {
         Ok(downloaded_count)
     }
 
-    pub async fn process_templates(&self) -> Result<()> {
-        let mut templates = self.templates.write().await;
-        let template_paths: Vec<PathBuf> = fs::read_dir(&self.template_dir)?
-            .filter_map(|entry| entry.ok().map(|e| e.path()))
-            .filter(|path| path.extension().map_or(false, |ext| ext == "png"))
-            .collect();
-
-        for path in template_paths {
-            let item_uid = path.file_stem()
-                .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?
-                .to_string_lossy()
-                .to_string();
-
-            if templates.contains_key(&item_uid) {
-                continue;
-            }
-
-            // Load and process the template
-            let mut template = imgcodecs::imread(path.to_str().unwrap(), imgcodecs::IMREAD_GRAYSCALE)?;
-            
-            // Process the template (resize, enhance edges, etc.)
-            opencv::imgproc::gaussian_blur(
-                &template,
-                &mut template,
-                opencv::core::Size::new(3, 3),
-                0.0,
-                0.0,
-                opencv::core::BORDER_DEFAULT,
-            )?;
-
-            // Store processed template
-            templates.insert(item_uid, template);
-            info!("Processed template: {}", path.display());
-        }
-
-        Ok(())
+    /// Items a custom template named `name` likely shows, best match first,
+    /// to suggest the uid to save it under with `add_template`.
+    pub async fn suggest_uids<P: PriceProvider>(&self, provider: &P, name: &str) -> Vec<(String, String)> {
+        provider.search_offline(name, 5).await
+            .into_iter()
+            .map(|hit| (hit.item.uid, hit.item.name))
+            .collect()
     }
 
     pub async fn get_template(&self, item_uid: &str) -> Option<Mat> {
@@ -194,6 +163,7 @@ impl TemplateManager {
         )?;
 
         // Add to in-memory cache
+        let template = preprocess_template(&template)?;
         let mut templates = self.templates.write().await;
         templates.insert(item_uid.to_string(), template);
 
@@ -201,6 +171,16 @@ impl TemplateManager {
         Ok(())
     }
 
+    /// Adds the image at `image_path`, e.g. a screenshot crop of the item,
+    /// as the template of `item_uid`.
+    pub async fn import_template(&self, item_uid: &str, image_path: &Path) -> Result<()> {
+        let template = imgcodecs::imread(image_path.to_str().unwrap(), imgcodecs::IMREAD_GRAYSCALE)?;
+        if template.empty() {
+            return Err(anyhow::anyhow!("Failed to read template image: {:?}", image_path));
+        }
+        self.add_template(item_uid, template).await
+    }
+
     pub async fn remove_template(&self, item_uid: &str) -> Result<()> {
         // Remove from in-memory cache
         let mut templates = self.templates.write().await;
@@ -217,12 +197,33 @@ impl TemplateManager {
         Ok(())
     }
 
-    pub async fn get_template_count(&self) -> usize {
-        let templates = self.templates.read().await;
-        templates.len()
+    /// Number of loaded templates, `None` while they're being loaded.
+    pub fn get_template_count(&self) -> Option<usize> {
+        self.templates.try_read().ok().map(|templates| templates.len())
     }
 }
 
+/// Blurs a grayscale image to reduce noise. Frames go through the same step
+/// before they're matched, see `capture::grab_frame`.
+pub fn preprocess_template(image: &Mat) -> Result<Mat> {
+    let mut processed = Mat::default();
+    opencv::imgproc::gaussian_blur(
+        image,
+        &mut processed,
+        opencv::core::Size::new(3, 3),
+        0.0,
+        0.0,
+        opencv::core::BORDER_DEFAULT,
+    )?;
+    Ok(processed)
+}
+
+// Loads a template image as grayscale and preprocesses it
+fn read_template(path: &Path) -> Result<Mat> {
+    let template = imgcodecs::imread(path.to_str().unwrap(), imgcodecs::IMREAD_GRAYSCALE)?;
+    preprocess_template(&template)
+}
+
 async fn download_image(url: &str, output_path: &Path) -> Result<()> {
     // Create a reqwest client
     let client = reqwest::Client::new();
@@ -248,4 +249,66 @@ async fn download_image(url: &str, output_path: &Path) -> Result<()> {
         .context("Failed to write image to file")?;
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::search_index::{self, SearchHit, SearchIndex};
+    use crate::api::{ApiError, Item};
+
+    // Answers offline searches from the search index fixtures
+    struct IndexedItems(SearchIndex);
+
+    impl PriceProvider for IndexedItems {
+        fn name(&self) -> &str {
+            "indexed"
+        }
+
+        async fn search_item(&self, _query: &str) -> Result<Vec<Item>, ApiError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_item_by_uid(&self, uid: &str) -> Result<Item, ApiError> {
+            Err(ApiError::NotFound(uid.to_string()))
+        }
+
+        async fn get_all_items(&self) -> Result<Vec<Item>, ApiError> {
+            Ok(search_index::tests::items())
+        }
+
+        async fn search_offline(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+            self.0.search(query, limit)
+        }
+    }
+
+    fn provider() -> IndexedItems {
+        let index = SearchIndex::new();
+        index.rebuild(&search_index::tests::items());
+        IndexedItems(index)
+    }
+
+    fn templates() -> TemplateManager {
+        TemplateManager::new(std::env::temp_dir().join(format!("tarkov-price-overlay-{}", std::process::id())).join("templates"))
+    }
+
+    #[tokio::test]
+    async fn suggests_the_best_matching_items_first() {
+        let suggestions = templates().suggest_uids(&provider(), "ledx").await;
+        assert_eq!(suggestions, [
+            ("5c0530ee86f774697952d952".to_string(), "LEDX Skin Transilluminator".to_string()),
+            ("5c052e6986f7746b207bc3c9".to_string(), "Portable defibrillator for the LEDX lab".to_string()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn suggests_items_by_localized_name_despite_typos() {
+        let templates = templates();
+        let provider = provider();
+        let uids = |suggestions: Vec<(String, String)>| suggestions.into_iter().map(|(uid, _)| uid).collect::<Vec<_>>();
+
+        assert_eq!(uids(templates.suggest_uids(&provider, "golden nekc chian").await), ["5734758f24597738025ee253"]);
+        assert_eq!(uids(templates.suggest_uids(&provider, "золотая").await), ["5734758f24597738025ee253"]);
+        assert!(templates.suggest_uids(&provider, "bitcoin").await.is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+//! Runs `mock-tarkov-market` on a free port for the duration of a test.
+
+// Each test binary uses a different part of the helpers
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const LOG_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct MockServer {
+    child: Child,
+    port: u16,
+    // Lines the server printed, one per request, e.g. "GET /api/v1/items/all? -> 304"
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Serves `tests/fixtures/<fixtures>`, e.g. `""` for the hand-written
+    /// fixtures or `"recorded"` for the recorded responses.
+    pub fn start(fixtures: &str) -> Self {
+        let port = free_port();
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixtures);
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mock-tarkov-market"))
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--fixtures")
+            .arg(&fixtures)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start mock-tarkov-market");
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let stdout = child.stdout.take().unwrap();
+        let lines = log.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                lines.lock().unwrap().push(line);
+            }
+        });
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(started.elapsed() < STARTUP_TIMEOUT, "mock-tarkov-market didn't start listening");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        Self { child, port, log }
+    }
+
+    /// Root of the Tarkov Market routes, for `TarkovMarketAPI::with_base_url`.
+    pub fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}/api/v1", self.port)
+    }
+
+    /// The tarkov.dev stand-in, for `TarkovDevAPI::with_endpoint`.
+    pub fn graphql_url(&self) -> String {
+        format!("http://127.0.0.1:{}/graphql", self.port)
+    }
+
+    /// Waits briefly for a logged request containing `needle`.
+    pub fn saw_request(&self, needle: &str) -> bool {
+        let started = Instant::now();
+        loop {
+            if self.log.lock().unwrap().iter().any(|line| line.contains(needle)) {
+                return true;
+            }
+            if started.elapsed() >= LOG_TIMEOUT {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Lets the OS pick a port; the listener is closed again before the server binds it
+fn free_port() -> u16 {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .expect("Failed to find a free port")
+}
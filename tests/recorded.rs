@@ -0,0 +1,81 @@
+//! Decoding of responses recorded from the live APIs, served by the mock
+//! server from `tests/fixtures/recorded`. The recordings mix camelCase
+//! fields, nulls, numbers sent as floats or strings, unknown fields and a few
+//! malformed entries that have to be skipped without failing the response.
+
+mod common;
+
+use common::MockServer;
+use tarkov_price_overlay::api::{TarkovDevAPI, TarkovMarketAPI};
+
+const MASKA_UID: &str = "5c0e874186f7745dc7616606";
+const CHAIN_UID: &str = "5734758f24597738025ee253";
+const TANK_UID: &str = "5d1b36a186f7742523398433";
+
+fn tarkov_market(server: &MockServer) -> TarkovMarketAPI {
+    TarkovMarketAPI::new("recorded".to_string()).with_base_url(&server.base_url())
+}
+
+#[tokio::test]
+async fn tarkov_market_list_skips_malformed_entries() {
+    let server = MockServer::start("recorded");
+    let api = tarkov_market(&server);
+
+    // Of five entries, one has no uid and one an object as its price
+    let items = api.get_all_items().await.unwrap();
+    let mut uids: Vec<&str> = items.iter().map(|item| item.uid.as_str()).collect();
+    uids.sort_unstable();
+    assert_eq!(uids, [MASKA_UID, CHAIN_UID, TANK_UID]);
+}
+
+#[tokio::test]
+async fn tarkov_market_item_reads_lenient_fields() {
+    let server = MockServer::start("recorded");
+    let api = tarkov_market(&server);
+
+    let item = api.get_item_by_uid(CHAIN_UID).await.unwrap();
+    assert_eq!(item.short_name, "Chain");
+    // Float price, string base price and null 7 day average
+    assert_eq!(item.price, 33000);
+    assert_eq!(item.base_price, 41090);
+    assert_eq!(item.avg_7days_price, 0);
+    assert_eq!(item.avg_24h_price, 34650);
+    assert_eq!(item.trader_price, 25476);
+    assert_eq!(item.tags, ["Barter", "Valuables"]);
+    assert!(item.is_functional);
+}
+
+#[tokio::test]
+async fn tarkov_market_keeps_unknown_fields() {
+    let server = MockServer::start("recorded");
+    let api = tarkov_market(&server);
+
+    let items = api.get_all_items().await.unwrap();
+    let maska = items.iter().find(|item| item.uid == MASKA_UID).unwrap();
+    assert!(maska.extra.contains_key("wikiLink"), "{:?}", maska.extra.keys());
+}
+
+#[tokio::test]
+async fn tarkov_dev_list_skips_null_and_malformed_entries() {
+    let server = MockServer::start("recorded");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    // Of seven entries, one is null and one has no name
+    let items = api.refresh_all_items().await.unwrap();
+    assert_eq!(items.len(), 5);
+    assert!(items.iter().all(|item| !item.uid.ends_with("-broken")));
+    assert!(items.iter().any(|item| item.uid == CHAIN_UID && item.price == 33000));
+}
+
+#[tokio::test]
+async fn tarkov_dev_recipes_skip_removed_items() {
+    let server = MockServer::start("recorded");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let recipes = api.get_recipes().await.unwrap();
+    let barter = recipes.iter().find(|recipe| recipe.id == "5ac3b86a86f77461491d1ad8").unwrap();
+    // The null item of the second requirement is dropped
+    assert_eq!(barter.inputs.len(), 1);
+    assert_eq!(barter.inputs[0].uid, CHAIN_UID);
+    assert_eq!(barter.inputs[0].count, 4);
+}
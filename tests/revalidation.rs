@@ -0,0 +1,43 @@
+//! Conditional requests for the full Tarkov Market item list: a refresh
+//! sends the validators of the cached list and a 304 serves the cached copy.
+
+mod common;
+
+use common::MockServer;
+use tarkov_price_overlay::api::{Item, TarkovMarketAPI};
+
+fn prices(items: &[Item]) -> Vec<(String, i32)> {
+    let mut prices: Vec<(String, i32)> = items.iter().map(|item| (item.uid.clone(), item.price)).collect();
+    prices.sort();
+    prices
+}
+
+#[tokio::test]
+async fn refresh_revalidates_the_cached_list() {
+    let server = MockServer::start("");
+    let api = TarkovMarketAPI::new("test".to_string()).with_base_url(&server.base_url());
+
+    let first = api.refresh_all_items().await.unwrap();
+    assert_eq!(first.len(), 4);
+    assert!(server.saw_request("GET /api/v1/items/all?"));
+    assert!(!server.saw_request("-> 304"));
+
+    let second = api.refresh_all_items().await.unwrap();
+    assert!(server.saw_request("-> 304"), "the second refresh wasn't conditional");
+    assert_eq!(prices(&second), prices(&first));
+    assert!(second.iter().all(|item| !item.stale));
+}
+
+#[tokio::test]
+async fn cleared_cache_downloads_the_list_again() {
+    let server = MockServer::start("");
+    let api = TarkovMarketAPI::new("test".to_string()).with_base_url(&server.base_url());
+
+    api.refresh_all_items().await.unwrap();
+    api.clear_cache().await;
+
+    // Without cached validators the request isn't conditional
+    let items = api.refresh_all_items().await.unwrap();
+    assert_eq!(items.len(), 4);
+    assert!(!server.saw_request("-> 304"));
+}
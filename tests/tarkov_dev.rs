@@ -0,0 +1,83 @@
+//! The tarkov.dev GraphQL backend against the mock server's fixtures.
+
+mod common;
+
+use common::MockServer;
+use tarkov_price_overlay::api::{ApiError, RecipeKind, TarkovDevAPI};
+
+const CHAIN_UID: &str = "5734758f24597738025ee253";
+const BS_AMMO_UID: &str = "56dff3afd2720bba668b4567";
+
+#[tokio::test]
+async fn item_by_uid_maps_the_graphql_fields() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let item = api.get_item_by_uid(CHAIN_UID).await.unwrap();
+    assert_eq!(item.uid, CHAIN_UID);
+    assert_eq!(item.name, "Golden neck chain");
+    assert_eq!(item.short_name, "Chain");
+    assert_eq!(item.price, 33000);
+    assert_eq!(item.avg_24h_price, 34650);
+    assert_eq!(item.base_price, 41090);
+    assert_eq!(item.trader_name, "Therapist");
+    assert_eq!(item.trader_price, 25476);
+    assert!(!item.stale);
+}
+
+#[tokio::test]
+async fn unknown_uid_is_not_found() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let result = api.get_item_by_uid("000000000000000000000000").await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))), "{:?}", result);
+}
+
+#[tokio::test]
+async fn items_by_uids_returns_only_the_requested_items() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let uids = vec![CHAIN_UID.to_string(), BS_AMMO_UID.to_string()];
+    let items = api.get_items_by_uids(&uids).await.unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[CHAIN_UID].name, "Golden neck chain");
+    assert_eq!(items[BS_AMMO_UID].short_name, "BS");
+}
+
+#[tokio::test]
+async fn search_queries_by_name_without_a_local_index() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let items = api.search_item("neck chain").await.unwrap();
+    let uids: Vec<&str> = items.iter().map(|item| item.uid.as_str()).collect();
+    assert_eq!(uids, [CHAIN_UID]);
+}
+
+#[tokio::test]
+async fn search_uses_the_index_after_a_refresh() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let all_items = api.refresh_all_items().await.unwrap();
+    assert_eq!(all_items.len(), 5);
+
+    // A typo the API's substring search wouldn't match
+    let items = api.search_item("golden nekc chain").await.unwrap();
+    assert_eq!(items.first().map(|item| item.uid.as_str()), Some(CHAIN_UID));
+    assert_eq!(items[0].price, 33000);
+}
+
+#[tokio::test]
+async fn recipes_include_crafts_and_barters() {
+    let server = MockServer::start("");
+    let api = TarkovDevAPI::with_endpoint(&server.graphql_url());
+
+    let recipes = api.get_recipes().await.unwrap();
+    assert_eq!(recipes.len(), 2);
+    assert!(recipes.iter().any(|recipe| matches!(recipe.kind, RecipeKind::Craft { .. })));
+    assert!(recipes.iter().any(|recipe| matches!(recipe.kind, RecipeKind::Barter { .. })));
+    assert!(recipes.iter().all(|recipe| !recipe.inputs.is_empty() && !recipe.outputs.is_empty()));
+}